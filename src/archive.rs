@@ -0,0 +1,267 @@
+//! Reading replays straight out of zip and tar(.gz) archives.
+//!
+//! Archives are treated like directories: the replay `Demos/x.replay` in `friends.zip` is
+//! `friends.zip/Demos/x.replay`, and that path can be given to `parse_replay_file` like any other.
+//! Nothing gets extracted to disk.
+//!
+//! The last archive read from is kept open (per thread), with the offsets of its entries, so reading
+//! all of its replays one after another doesn't start over for each one. A tar.gz can only be read
+//! forwards, so it's fastest to read its replays in the order they're stored in; going back to an
+//! earlier entry decompresses it again from the start.
+
+use flate2::read::GzDecoder;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else {
+        None
+    }
+}
+
+/// Whether the path names a zip or tar(.gz) archive, going by its extension.
+pub fn is_archive(path: &Path) -> bool {
+    archive_kind(path).is_some()
+}
+
+/// Split a path like `friends.zip/Demos/x.replay` into the archive file and the entry in it.
+/// `None` if no parent of the path is an archive file.
+pub fn split_archive_path(path: &Path) -> Option<(&Path, &Path)> {
+    path.ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive(ancestor) && ancestor.is_file())
+        .and_then(|archive| path.strip_prefix(archive).ok().map(|entry| (archive, entry)))
+}
+
+// tar entries are often stored as `./Demos/x.replay`
+fn normal_path(path: &Path) -> PathBuf {
+    path.components().filter(|c| matches!(c, Component::Normal(_))).collect()
+}
+
+fn open_tar(archive: &Path, kind: ArchiveKind) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = BufReader::new(File::open(archive)?);
+    let reader: Box<dyn Read> = match kind {
+        ArchiveKind::TarGz => Box::new(GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// The `.replay` files in an archive, as `archive/entry` paths.
+pub fn archive_replays(archive: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries: Vec<PathBuf> = Vec::new();
+    match archive_kind(archive) {
+        Some(ArchiveKind::Zip) => {
+            let mut zip = zip::ZipArchive::new(File::open(archive)?)?;
+            for ind in 0..zip.len() {
+                let file = zip.by_index(ind)?;
+                // skips names that would point outside the archive, e.g. `../x.replay`
+                if let Some(name) = file.enclosed_name().filter(|_| file.is_file()) {
+                    entries.push(name.to_path_buf());
+                }
+            }
+        },
+        Some(kind) => {
+            for entry in open_tar(archive, kind)?.entries()? {
+                let entry = entry?;
+                if entry.header().entry_type().is_file() {
+                    entries.push(normal_path(&entry.path()?));
+                }
+            }
+        },
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't a zip or tar archive", archive.display()))),
+    }
+    Ok(entries.into_iter()
+        .filter(|entry| entry.extension().is_some_and(|ex| ex == "replay"))
+        .map(|entry| archive.join(entry))
+        .collect())
+}
+
+// `entry -> (offset, size)` of each file's data in the (uncompressed) tar
+fn tar_index(archive: &Path, kind: ArchiveKind) -> io::Result<HashMap<PathBuf, (u64, u64)>> {
+    let mut index = HashMap::new();
+    for entry in open_tar(archive, kind)?.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            index.insert(normal_path(&entry.path()?), (entry.raw_file_position(), entry.size()));
+        }
+    }
+    Ok(index)
+}
+
+enum ArchiveContents {
+    Zip(zip::ZipArchive<BufReader<File>>),
+    Tar(HashMap<PathBuf, (u64, u64)>),
+    /// The decompressed stream can't seek, so keep reading it forwards from `position`
+    TarGz {
+        index: HashMap<PathBuf, (u64, u64)>,
+        reader: Box<GzDecoder<BufReader<File>>>,
+        position: u64,
+    },
+}
+
+struct OpenArchive {
+    path: PathBuf,
+    // to notice the archive being replaced while it's open
+    modified: Option<SystemTime>,
+    contents: ArchiveContents,
+}
+
+thread_local! {
+    static OPEN_ARCHIVE: RefCell<Option<OpenArchive>> = const { RefCell::new(None) };
+}
+
+fn not_found(archive: &Path, entry: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} isn't in {}", entry.display(), archive.display()))
+}
+
+impl OpenArchive {
+    fn open(archive: &Path, modified: Option<SystemTime>) -> io::Result<Self> {
+        let contents = match archive_kind(archive) {
+            Some(ArchiveKind::Zip) => ArchiveContents::Zip(zip::ZipArchive::new(BufReader::new(File::open(archive)?))?),
+            Some(ArchiveKind::Tar) => ArchiveContents::Tar(tar_index(archive, ArchiveKind::Tar)?),
+            Some(ArchiveKind::TarGz) => ArchiveContents::TarGz {
+                index: tar_index(archive, ArchiveKind::TarGz)?,
+                reader: Box::new(GzDecoder::new(BufReader::new(File::open(archive)?))),
+                position: 0,
+            },
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't a zip or tar archive", archive.display()))),
+        };
+        Ok(Self {
+            path: archive.to_path_buf(),
+            modified,
+            contents,
+        })
+    }
+
+    fn read(&mut self, entry: &Path) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        match &mut self.contents {
+            ArchiveContents::Zip(zip) => {
+                // zip entry names always use `/`
+                let name = entry.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+                zip.by_name(&name)?.read_to_end(&mut data)?;
+            },
+            ArchiveContents::Tar(index) => {
+                let &(offset, size) = index.get(entry).ok_or_else(|| not_found(&self.path, entry))?;
+                let mut file = File::open(&self.path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                file.take(size).read_to_end(&mut data)?;
+            },
+            ArchiveContents::TarGz { index, reader, position } => {
+                let &(offset, size) = index.get(entry).ok_or_else(|| not_found(&self.path, entry))?;
+                if offset < *position {
+                    **reader = GzDecoder::new(BufReader::new(File::open(&self.path)?));
+                    *position = 0;
+                }
+                io::copy(&mut reader.by_ref().take(offset - *position), &mut io::sink())?;
+                reader.by_ref().take(size).read_to_end(&mut data)?;
+                *position = offset + size;
+            },
+        }
+        Ok(data)
+    }
+}
+
+/// Read one entry of an archive into memory.
+pub fn read_archive_entry(archive: &Path, entry: &Path) -> io::Result<Vec<u8>> {
+    let modified = fs::metadata(archive)?.modified().ok();
+    OPEN_ARCHIVE.with(|open| {
+        let mut open = open.borrow_mut();
+        let oa = match open.take() {
+            Some(oa) if oa.path == archive && oa.modified == modified => oa,
+            _ => OpenArchive::open(archive, modified)?,
+        };
+        let result = open.insert(oa).read(entry);
+        if result.is_err() {
+            // don't keep a reader in an unknown position around
+            *open = None;
+        }
+        result
+    })
+}
+
+/// Read a replay into memory, whether it's a plain file or an `archive/entry` path.
+pub fn read_replay<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        if let Some((archive, entry)) = split_archive_path(path) {
+            return read_archive_entry(archive, entry);
+        }
+    }
+    fs::read(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn entries() -> Vec<(String, Vec<u8>)> {
+        (0..4).map(|ind| (format!("Demos/{}.replay", ind), vec![ind as u8; 1000 + ind])).collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("replay_stats_rl-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn check_reads(archive: &Path) {
+        let mut found = archive_replays(archive).unwrap();
+        found.sort();
+        assert_eq!(found, entries().iter().map(|(name, _)| archive.join(name)).collect::<Vec<_>>());
+        // forwards, backwards and repeated, through the kept open archive
+        for ind in [0, 1, 3, 2, 0, 3, 3] {
+            let (name, data) = &entries()[ind];
+            assert_eq!(&read_replay(archive.join(name)).unwrap(), data);
+        }
+        assert!(read_replay(archive.join("Demos/missing.replay")).is_err());
+    }
+
+    #[test]
+    fn reads_tar_gz_entries_in_any_order() {
+        let archive = temp_dir("targz").join("replays.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(&archive).unwrap(), flate2::Compression::default()));
+        for (name, data) in entries() {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, format!("./{}", name), data.as_slice()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        check_reads(&archive);
+    }
+
+    #[test]
+    fn reads_zip_entries_in_any_order() {
+        let archive = temp_dir("zip").join("replays.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        for (name, data) in entries() {
+            zip.start_file(name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(&data).unwrap();
+        }
+        zip.finish().unwrap();
+        check_reads(&archive);
+    }
+}
@@ -0,0 +1,130 @@
+use boxcars::{Attribute, Replay};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::metrics::{MetricContext, ReplayMetric};
+use crate::player_key::PlayerKey;
+use crate::{get_object_id, ChangeEvent, LifetimeList, Metadata, PlayerResults, BEHIND_VIEW_OBJECT, SWIVEL_OBJECT};
+
+/// A player's camera profile, from `TAGame.CameraSettingsActor_TA:ProfileSettings`,
+/// plus whether they ever had swivel or rear view enabled during the match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraSettings {
+    pub fov: f32,
+    pub height: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub stiffness: f32,
+    pub swivel_speed: f32,
+    pub transition_speed: Option<f32>,
+    pub used_behind_view: bool,
+    pub used_swivel: bool,
+}
+
+impl CameraSettings {
+    /// Whether FOV, distance, height or stiffness differ. The other fields are ignored,
+    /// since they don't change what the player actually sees much.
+    pub fn differs_from(&self, other: &CameraSettings) -> bool {
+        let changed = |a: f32, b: f32| (a - b).abs() > 0.001;
+        changed(self.fov, other.fov)
+            || changed(self.distance, other.distance)
+            || changed(self.height, other.height)
+            || changed(self.stiffness, other.stiffness)
+    }
+}
+
+/// A camera profile change for a player, found between two consecutive replays.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraSettingsChange {
+    pub date: NaiveDate,
+    pub before: CameraSettings,
+    pub after: CameraSettings,
+}
+
+/// Each player's camera settings, for when the ballcam numbers aren't needed.
+pub struct CameraSettingsMetric;
+
+impl ReplayMetric for CameraSettingsMetric {
+    type Output = CameraSettings;
+
+    fn compute(&self, ctx: &MetricContext) -> HashMap<PlayerKey, CameraSettings> {
+        ctx.players.iter()
+            .filter_map(|(pid, idx_list)| {
+                get_camera_settings(ctx.lifetimes, ctx.replay, idx_list, ctx.disconnects.get(pid))
+                    .map(|cam| (pid.clone(), cam))
+            })
+            .collect()
+    }
+}
+
+/// Get the camera settings from a player's camera lifetimes (from `player_id_buckets`).
+///
+/// If the profile is replicated more than once, the latest one before the player disconnected wins.
+/// Returns `None` if the replay never replicated a profile for this player.
+pub(crate) fn get_camera_settings(ltl: &LifetimeList, replay: &Replay, idx_list: &[usize], disconnect_time: Option<&f32>) -> Option<CameraSettings> {
+    let profile_id = get_object_id(replay, "TAGame.CameraSettingsActor_TA:ProfileSettings")?;
+    let behind_view_id = get_object_id(replay, BEHIND_VIEW_OBJECT);
+    let swivel_id = get_object_id(replay, SWIVEL_OBJECT);
+
+    let mut latest_profile: Option<(usize, &boxcars::CamSettings)> = None;
+    let mut used_behind_view = false;
+    let mut used_swivel = false;
+
+    for &cfi in idx_list {
+        for ev in ltl.list[cfi].events.iter() {
+            if disconnect_time.is_some_and(|&dt| ev.time >= dt) {
+                break;
+            }
+            if let ChangeEvent::U(ua) = &ev.event {
+                match &ua.attribute {
+                    Attribute::CamSettings(cam) if ua.object_id.0 == profile_id
+                        && latest_profile.is_none_or(|(frame, _)| frame <= ev.frame) => {
+                        latest_profile = Some((ev.frame, cam));
+                    },
+                    Attribute::Boolean(true) if Some(ua.object_id.0) == behind_view_id => used_behind_view = true,
+                    Attribute::Boolean(true) if Some(ua.object_id.0) == swivel_id => used_swivel = true,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    latest_profile.map(|(_, cam)| CameraSettings {
+        fov: cam.fov,
+        height: cam.height,
+        pitch: cam.angle,
+        distance: cam.distance,
+        stiffness: cam.stiffness,
+        swivel_speed: cam.swivel,
+        transition_speed: cam.transition,
+        used_behind_view,
+        used_swivel,
+    })
+}
+
+/// Collect every player's camera settings across replays, sorted by date.
+pub fn camera_history(data: &[(Metadata, PlayerResults)]) -> HashMap<PlayerKey, Vec<(NaiveDate, CameraSettings)>> {
+    let mut ret: HashMap<PlayerKey, Vec<(NaiveDate, CameraSettings)>> = HashMap::new();
+    for (md, hm) in data {
+        for (pid, res) in hm {
+            if let Some(cam) = &res.camera_settings {
+                ret.entry(pid.clone()).or_default().push((md.date, cam.clone()));
+            }
+        }
+    }
+    ret.values_mut().for_each(|hist| hist.sort_by_key(|(date, _)| *date));
+    ret
+}
+
+/// Find the dates where a player's FOV/distance/height/stiffness changed (see `CameraSettings::differs_from`).
+pub fn camera_changes(history: &[(NaiveDate, CameraSettings)]) -> Vec<CameraSettingsChange> {
+    history.windows(2)
+        .filter(|pair| pair[1].1.differs_from(&pair[0].1))
+        .map(|pair| CameraSettingsChange {
+            date: pair[1].0,
+            before: pair[0].1.clone(),
+            after: pair[1].1.clone(),
+        })
+        .collect()
+}
@@ -0,0 +1,269 @@
+use glob::Pattern;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::archive::{archive_replays, is_archive};
+
+/// Where to look for replays: any number of directories, optionally searched recursively and
+/// narrowed down with glob patterns, plus an optional list file of explicit replay paths.
+/// Zip and tar archives (as roots, in the directories, or in the list file) count as directories
+/// of replays, see `archive`. Every replay in an archive is found, wherever it is in the archive,
+/// even without `recursive` (which only decides whether subdirectories on disk are searched, never
+/// following symlinked ones).
+/// Archives that can't be read are skipped with a warning.
+#[derive(Debug, Clone, Default)]
+pub struct ReplaySource {
+    pub roots: Vec<PathBuf>,
+    pub recursive: bool,
+    /// If any are given, a replay has to match one of them
+    pub include: Vec<Pattern>,
+    /// A replay matching any of these is skipped
+    pub exclude: Vec<Pattern>,
+    /// A file with one replay path per line. Blank lines and lines starting with `#` are ignored,
+    /// relative paths are relative to the list file. These are used as is, without the patterns.
+    pub list_file: Option<PathBuf>,
+}
+
+impl ReplaySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn root<P: AsRef<Path>>(&mut self, root: P) -> &mut Self {
+        self.roots.push(root.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn recursive(&mut self, recursive: bool) -> &mut Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Patterns are matched against the path relative to its root, e.g. `2023-*` or `ranked/**/*.replay`.
+    pub fn include(&mut self, pattern: &str) -> Result<&mut Self, glob::PatternError> {
+        self.include.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    pub fn exclude(&mut self, pattern: &str) -> Result<&mut Self, glob::PatternError> {
+        self.exclude.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    pub fn list_file<P: AsRef<Path>>(&mut self, list_file: P) -> &mut Self {
+        self.list_file = Some(list_file.as_ref().to_path_buf());
+        self
+    }
+
+    fn matches(&self, relative: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pat| pat.matches_path(relative)))
+            && !self.exclude.iter().any(|pat| pat.matches_path(relative))
+    }
+
+    /// Whether a single replay file would be found under one of the roots, e.g. for one that was just saved.
+    /// Relative paths and roots are taken relative to the working directory, so a relative root
+    /// accepts the absolute paths a file watcher reports.
+    pub fn accepts(&self, path: &Path) -> bool {
+        if path.extension().is_none_or(|ex| ex != "replay") {
+            return false;
+        }
+        let path = absolute(path);
+        self.roots.iter().any(|root| {
+            path.strip_prefix(absolute(root)).is_ok_and(|relative| {
+                (self.recursive || relative.components().count() == 1) && self.matches(relative)
+            })
+        })
+    }
+
+    /// Every replay file from the roots and the list file, sorted and without duplicates.
+    /// Fails if a directory or the list file can't be read, but not for an archive.
+    pub fn discover(&self) -> io::Result<Vec<PathBuf>> {
+        let mut ret: Vec<PathBuf> = Vec::new();
+        for root in self.roots.iter() {
+            let mut found = Vec::new();
+            if is_archive(root) && root.is_file() {
+                found.extend(readable_archive_replays(root));
+            } else {
+                collect_replays(root, self.recursive, &mut found)?;
+            }
+            ret.extend(found.into_iter().filter(|path| self.matches(path.strip_prefix(root).unwrap_or(path))));
+        }
+        if let Some(list_file) = &self.list_file {
+            for path in read_list_file(list_file)? {
+                if is_archive(&path) && path.is_file() {
+                    ret.extend(readable_archive_replays(&path));
+                } else {
+                    ret.push(path);
+                }
+            }
+        }
+
+        ret.sort();
+        let mut seen = HashSet::new();
+        ret.retain(|path| seen.insert(path.clone()));
+        Ok(ret)
+    }
+}
+
+/// The path joined onto the working directory if it's relative, or as is if that can't be read.
+pub fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn with_path(err: io::Error, path: &Path) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+}
+
+// a broken archive shouldn't stop every other replay from being found
+fn readable_archive_replays(archive: &Path) -> Vec<PathBuf> {
+    archive_replays(archive).unwrap_or_else(|e| {
+        eprintln!("Skipping archive {}: {}", archive.display(), e);
+        vec![]
+    })
+}
+
+fn collect_replays(dir: &Path, recursive: bool, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir).map_err(|e| with_path(e, dir))? {
+        let entry = entry.map_err(|e| with_path(e, dir))?;
+        let path = entry.path();
+        // doesn't follow symlinks, so a link back up the tree can't loop forever
+        if entry.file_type().map_err(|e| with_path(e, &path))?.is_dir() {
+            if recursive {
+                collect_replays(&path, recursive, out)?;
+            }
+        } else if path.extension().is_some_and(|ex| ex == "replay") {
+            out.push(path);
+        } else if is_archive(&path) {
+            out.extend(readable_archive_replays(&path));
+        }
+    }
+    Ok(())
+}
+
+fn read_list_file(list_file: &Path) -> io::Result<Vec<PathBuf>> {
+    let contents = fs::read_to_string(list_file).map_err(|e| with_path(e, list_file))?;
+    let base = list_file.parent().unwrap_or(Path::new(""));
+    Ok(contents.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_relative_and_absolute_roots() {
+        let cwd = std::env::current_dir().unwrap();
+        let mut relative = ReplaySource::new();
+        relative.root("demos");
+        let mut absolute = ReplaySource::new();
+        absolute.root(cwd.join("demos"));
+
+        for source in [&relative, &absolute] {
+            assert!(source.accepts(&cwd.join("demos/x.replay")));
+            assert!(source.accepts(Path::new("demos/x.replay")));
+            assert!(source.accepts(Path::new("./demos/x.replay")));
+            assert!(!source.accepts(&cwd.join("demos/x.txt")));
+            assert!(!source.accepts(&cwd.join("other/x.replay")));
+            // not recursive
+            assert!(!source.accepts(&cwd.join("demos/ranked/x.replay")));
+        }
+    }
+
+    #[test]
+    fn accepts_checks_patterns_relative_to_the_root() {
+        let cwd = std::env::current_dir().unwrap();
+        let mut source = ReplaySource::new();
+        source.root("demos").recursive(true).include("ranked/**").unwrap().exclude("**/old-*").unwrap();
+        assert!(source.accepts(&cwd.join("demos/ranked/x.replay")));
+        assert!(source.accepts(&cwd.join("demos/ranked/2023/x.replay")));
+        assert!(!source.accepts(&cwd.join("demos/casual/x.replay")));
+        assert!(!source.accepts(&cwd.join("demos/ranked/old-x.replay")));
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("replay_stats_rl-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(dir: &Path, files: &[&str]) {
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+    }
+
+    // the found paths relative to `dir`, as strings
+    fn relative(found: Vec<PathBuf>, dir: &Path) -> Vec<String> {
+        found.iter().map(|path| path.strip_prefix(dir).unwrap().to_string_lossy().replace('\\', "/")).collect()
+    }
+
+    #[test]
+    fn discover_searches_subdirectories_only_if_recursive() {
+        let dir = temp_dir("recursive");
+        touch(&dir, &["b.replay", "a.replay", "notes.txt", "ranked/c.replay", "ranked/2023/d.replay"]);
+
+        let mut source = ReplaySource::new();
+        source.root(&dir);
+        assert_eq!(relative(source.discover().unwrap(), &dir), ["a.replay", "b.replay"]);
+        source.recursive(true);
+        assert_eq!(relative(source.discover().unwrap(), &dir), ["a.replay", "b.replay", "ranked/2023/d.replay", "ranked/c.replay"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn discover_skips_symlinked_directories() {
+        let dir = temp_dir("symlinks");
+        let other = temp_dir("symlinks-other");
+        touch(&dir, &["a.replay"]);
+        touch(&other, &["elsewhere.replay"]);
+        std::os::unix::fs::symlink(&other, dir.join("linked")).unwrap();
+        // would loop forever if followed
+        std::os::unix::fs::symlink(&dir, dir.join("loop")).unwrap();
+
+        let mut source = ReplaySource::new();
+        source.root(&dir).recursive(true);
+        assert_eq!(relative(source.discover().unwrap(), &dir), ["a.replay"]);
+    }
+
+    #[test]
+    fn discover_matches_patterns_relative_to_the_root() {
+        let dir = temp_dir("patterns");
+        touch(&dir, &["ranked/a.replay", "ranked/old-b.replay", "casual/c.replay"]);
+
+        let mut source = ReplaySource::new();
+        source.root(&dir).recursive(true).include("ranked/*").unwrap().exclude("**/old-*").unwrap();
+        assert_eq!(relative(source.discover().unwrap(), &dir), ["ranked/a.replay"]);
+    }
+
+    #[test]
+    fn discover_reads_the_list_file_and_drops_duplicates() {
+        let dir = temp_dir("list");
+        touch(&dir, &["demos/a.replay", "demos/b.replay", "other/c.replay"]);
+        fs::write(dir.join("list.txt"), "# saved from the other pc\n\ndemos/a.replay\n  other/c.replay  \n#demos/b.replay\n").unwrap();
+
+        let mut source = ReplaySource::new();
+        source.root(dir.join("demos")).list_file(dir.join("list.txt"));
+        // a.replay is in both, b.replay only in the directory
+        assert_eq!(relative(source.discover().unwrap(), &dir), ["demos/a.replay", "demos/b.replay", "other/c.replay"]);
+    }
+
+    #[test]
+    fn discover_fails_for_a_missing_directory_or_list_file() {
+        let dir = temp_dir("missing");
+        let err = crate::get_replay_list(dir.join("nope")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("nope"));
+
+        let err = ReplaySource::new().list_file(dir.join("list.txt")).discover().unwrap_err();
+        assert!(err.to_string().contains("list.txt"));
+    }
+}
@@ -0,0 +1,110 @@
+use boxcars::Replay;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+use crate::resolver::PlayerResolver;
+use crate::player_key::PlayerKey;
+use crate::{object_name, parse_file, parse_lifetimes, DumpEvent, Event, LifetimeList};
+
+/// Which events to dump. Every filter that is set has to match.
+#[derive(Debug, Clone, Default)]
+pub struct DumpFilter {
+    /// Substring of the object name. Matches every event of a lifetime whose actor was created
+    /// from a matching object (e.g. `CameraSettingsActor`), or single updates of a matching
+    /// attribute (e.g. `bUsingSecondaryCamera`).
+    pub object: Option<String>,
+    pub actor_id: Option<i32>,
+    /// Inclusive frame range
+    pub frames: Option<(usize, usize)>,
+    /// Inclusive time range, in seconds
+    pub times: Option<(f32, f32)>,
+    /// Matches every actor `PlayerResolver` ties to the player.
+    pub player: Option<PlayerKey>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// The `DumpEvent` text format, grouped by lifetime
+    Text,
+    /// One JSON object per event
+    JsonLines,
+}
+
+impl DumpFilter {
+    fn matches_event(&self, ev: &Event, replay: &Replay, lifetime_object_matches: bool) -> bool {
+        if self.frames.is_some_and(|(start, end)| ev.frame < start || ev.frame > end) {
+            return false;
+        }
+        if self.times.is_some_and(|(start, end)| ev.time < start || ev.time > end) {
+            return false;
+        }
+        match &self.object {
+            Some(object) if !lifetime_object_matches => {
+                ev.event.object_id().is_some_and(|oid| object_name(replay, oid).contains(object.as_str()))
+            },
+            _ => true,
+        }
+    }
+}
+
+/// Get the lifetimes of every actor that belongs to a player (PRI, cameras, cars, car components).
+fn player_lifetimes(ltl: &LifetimeList, replay: &Replay, player: &PlayerKey) -> HashSet<usize> {
+    let resolver = PlayerResolver::new(ltl, replay);
+    ltl.list.iter()
+        .enumerate()
+        .filter(|(_, lt)| {
+            resolver.resolve_lifetime(lt, lt.start_frame()).is_some_and(|pid| PlayerKey::from(&pid) == *player)
+        })
+        .map(|(ind, _)| ind)
+        .collect()
+}
+
+/// Write every event of a replay that matches the filter to `out`, lifetime by lifetime,
+/// in the order the lifetimes started.
+pub fn dump_replay<P: AsRef<Path>, W: Write>(replay_file: P, filter: &DumpFilter, format: DumpFormat, out: &mut W) -> Result<(), ()> {
+    let replay = parse_file(replay_file).map_err(|e| {
+        eprintln!("\nHIDDEN ERROR:\n{}\n\n", e);
+    })?;
+    let lifetimes = parse_lifetimes(&replay);
+    let player_filter = filter.player.as_ref().map(|player| player_lifetimes(&lifetimes, &replay, player));
+
+    let mut order: Vec<usize> = (0..lifetimes.list.len()).collect();
+    order.sort_by_key(|&ll| lifetimes.list[ll].events[0].frame);
+
+    for ll in order {
+        let lifetime = &lifetimes.list[ll];
+        let actor_id = lifetime.events[0].event.actor_id();
+        if filter.actor_id.is_some_and(|aid| aid != actor_id) {
+            continue;
+        }
+        if player_filter.as_ref().is_some_and(|players| !players.contains(&ll)) {
+            continue;
+        }
+
+        let lifetime_object = lifetime.events[0].event.object_id().map(|oid| object_name(&replay, oid));
+        let lifetime_object_matches = filter.object.as_ref()
+            .is_none_or(|object| lifetime_object.is_some_and(|lo| lo.contains(object.as_str())));
+        let events: Vec<&Event> = lifetime.events.iter()
+            .filter(|ev| filter.matches_event(ev, &replay, lifetime_object_matches))
+            .collect();
+        if events.is_empty() {
+            continue;
+        }
+
+        let written = match format {
+            DumpFormat::Text => {
+                writeln!(out, "==================== Actor {} ({}) ====================", actor_id, lifetime_object.unwrap_or("N/A"))
+                    .and_then(|_| events.iter().try_for_each(|ev| writeln!(out, "{}\n------------------------", ev.dump(&replay))))
+            },
+            DumpFormat::JsonLines => {
+                events.iter().try_for_each(|ev| writeln!(out, "{}", ev.dump_json(&replay)))
+            },
+        };
+        // the reader went away (e.g. piped into head), nothing left to do
+        if written.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use plotters::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::metrics::{MetricContext, ReplayMetric};
+use crate::player_key::PlayerKey;
+use crate::ToggleTimeline;
+
+/// Longest stretch the histograms show on their own, longer ones go in the last bar.
+pub const HISTOGRAM_MAX_SECONDS: u32 = 30;
+
+/// How long each of a player's ballcam on and ballcam off stretches lasted, in seconds.
+/// Only stretches between two toggles count, since the state before the first toggle is a guess
+/// and the last stretch is cut short by the end of the game.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DwellTimes {
+    pub on: Vec<f32>,
+    pub off: Vec<f32>,
+}
+
+impl DwellTimes {
+    pub fn extend(&mut self, other: &DwellTimes) {
+        self.on.extend_from_slice(&other.on);
+        self.off.extend_from_slice(&other.off);
+    }
+
+    pub fn on_stats(&self) -> Option<DwellStats> {
+        DwellStats::from_times(&self.on)
+    }
+
+    pub fn off_stats(&self) -> Option<DwellStats> {
+        DwellStats::from_times(&self.off)
+    }
+}
+
+/// Summary of a list of stretch lengths, in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DwellStats {
+    pub count: usize,
+    pub mean: f32,
+    pub p10: f32,
+    pub p25: f32,
+    pub median: f32,
+    pub p75: f32,
+    pub p90: f32,
+    pub max: f32,
+}
+
+// linear interpolation between the closest ranks
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    let rank = p * (sorted.len() - 1) as f32;
+    let below = rank.floor() as usize;
+    let above = rank.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f32)
+}
+
+impl DwellStats {
+    pub fn from_times(times: &[f32]) -> Option<Self> {
+        if times.is_empty() {
+            return None;
+        }
+        let mut sorted = times.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        Some(Self {
+            count: sorted.len(),
+            mean: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            p10: percentile(&sorted, 0.1),
+            p25: percentile(&sorted, 0.25),
+            median: percentile(&sorted, 0.5),
+            p75: percentile(&sorted, 0.75),
+            p90: percentile(&sorted, 0.9),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+/// Each player's ballcam stretches in one replay. Combine replays with `DwellTimes::extend`.
+pub struct DwellMetric;
+
+impl ReplayMetric for DwellMetric {
+    type Output = DwellTimes;
+
+    fn compute(&self, ctx: &MetricContext) -> HashMap<PlayerKey, DwellTimes> {
+        ctx.players.iter()
+            .map(|(pid, idx_list)| {
+                let timeline = ToggleTimeline::ballcam(ctx.lifetimes, ctx.replay, pid, idx_list, ctx.disconnects.get(pid));
+                (pid.clone(), dwell_times(&timeline))
+            })
+            .collect()
+    }
+}
+
+fn dwell_times(timeline: &ToggleTimeline) -> DwellTimes {
+    let mut ret = DwellTimes::default();
+    for pair in timeline.changes[1..].windows(2) {
+        let length = pair[1].0 - pair[0].0;
+        if length <= 0f32 {
+            continue;
+        }
+        if pair[0].1 {
+            ret.on.push(length);
+        } else {
+            ret.off.push(length);
+        }
+    }
+    ret
+}
+
+// one bar per second, everything past HISTOGRAM_MAX_SECONDS in the last one
+fn histogram_bins(times: &[f32]) -> Vec<(u32, u32)> {
+    let mut counts = vec![0u32; HISTOGRAM_MAX_SECONDS as usize + 1];
+    for &time in times {
+        counts[(time.max(0f32) as u32).min(HISTOGRAM_MAX_SECONDS) as usize] += 1;
+    }
+    counts.into_iter().enumerate().map(|(bin, count)| (bin as u32, count)).collect()
+}
+
+/// Histograms of the ballcam on (top) and off (bottom) stretch lengths, to `outputs/{file}.png`.
+pub fn plot_dwell_histogram(times: &DwellTimes, file: &str) {
+    if times.on.is_empty() && times.off.is_empty() {
+        eprintln!("No ballcam toggles for {}, skipping it", file);
+        return;
+    }
+
+    let fname = format!("outputs/{}.png", file);
+    let root_area = BitMapBackend::new(&fname, (600*2, 2*400))
+        .into_drawing_area();
+    root_area.fill(&WHITE).unwrap();
+    let (top_area, bottom_area) = root_area.split_vertically(400);
+
+    for (area, stretches, label, color) in [
+        (&top_area, &times.on, "ballcam on", BLUE),
+        (&bottom_area, &times.off, "ballcam off", RED),
+    ] {
+        let bins = histogram_bins(stretches);
+        let max_count = bins.iter().map(|&(_, count)| count).max().unwrap_or(0).max(1);
+
+        let mut ctx = ChartBuilder::on(area)
+            .set_label_area_size(LabelAreaPosition::Left, 40)
+            .set_label_area_size(LabelAreaPosition::Bottom, 40)
+            .caption(format!("{} seconds ({}+ in the last bar) - {}", label, HISTOGRAM_MAX_SECONDS, file), ("sans-serif", 30))
+            .build_cartesian_2d((0u32..HISTOGRAM_MAX_SECONDS + 1).into_segmented(), 0u32..max_count + max_count / 10 + 1)
+            .unwrap();
+
+        ctx.configure_mesh().draw().unwrap();
+
+        ctx.draw_series(
+            Histogram::vertical(&ctx)
+                .style(color.filled())
+                .margin(2)
+                .data(bins),
+        ).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeline(changes: &[(f32, bool)], end: f32) -> ToggleTimeline {
+        ToggleTimeline { changes: changes.to_vec(), end }
+    }
+
+    #[test]
+    fn percentile_of_one_value() {
+        assert_eq!(percentile(&[3.0], 0.0), 3.0);
+        assert_eq!(percentile(&[3.0], 0.5), 3.0);
+        assert_eq!(percentile(&[3.0], 1.0), 3.0);
+    }
+
+    #[test]
+    fn percentile_ends_and_interpolation() {
+        let sorted = [1.0, 2.0, 4.0, 8.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 1.0), 8.0);
+        // rank 1.5, halfway between 2 and 4
+        assert_eq!(percentile(&sorted, 0.5), 3.0);
+        // rank 0.3
+        assert!((percentile(&sorted, 0.1) - 1.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stats_of_nothing_and_unsorted_times() {
+        assert_eq!(DwellStats::from_times(&[]), None);
+        let stats = DwellStats::from_times(&[5.0, 1.0, 3.0]).unwrap();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.median, 3.0);
+        assert_eq!(stats.max, 5.0);
+        assert_eq!(stats.mean, 3.0);
+    }
+
+    #[test]
+    fn no_complete_stretches() {
+        // never toggled, or toggled once: every stretch touches the start or the end
+        assert_eq!(dwell_times(&timeline(&[(0.0, false)], 100.0)), DwellTimes::default());
+        assert_eq!(dwell_times(&timeline(&[(0.0, false), (10.0, true)], 100.0)), DwellTimes::default());
+    }
+
+    #[test]
+    fn stretches_between_toggles() {
+        let times = dwell_times(&timeline(&[(0.0, false), (10.0, true), (15.0, false), (17.5, true), (30.0, false)], 100.0));
+        assert_eq!(times.on, vec![5.0, 12.5]);
+        assert_eq!(times.off, vec![2.5]);
+    }
+
+    #[test]
+    fn zero_length_stretches_are_skipped() {
+        let times = dwell_times(&timeline(&[(0.0, false), (10.0, true), (10.0, false), (12.0, true)], 100.0));
+        assert!(times.on.is_empty());
+        assert_eq!(times.off, vec![2.0]);
+    }
+
+    #[test]
+    fn histogram_puts_long_stretches_in_the_last_bar() {
+        let bins = histogram_bins(&[0.2, 0.9, 1.0, 29.9, 30.0, 500.0]);
+        assert_eq!(bins.len(), HISTOGRAM_MAX_SECONDS as usize + 1);
+        assert_eq!(bins[0], (0, 2));
+        assert_eq!(bins[1], (1, 1));
+        assert_eq!(bins[29], (29, 1));
+        assert_eq!(bins[30], (30, 2));
+    }
+}
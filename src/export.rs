@@ -0,0 +1,86 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::player_key::PlayerKey;
+use crate::{Metadata, PlayerResult, PlayerResults};
+
+/// Columns of the CSV export, one row per player per replay. Times are in seconds.
+pub const EXPORT_COLUMNS: [&str; 20] = [
+    "file", "date", "playlist", "player",
+    "ballcam_all", "ballcam_with_freeze", "ballcam_active_only",
+    "total_time_all", "total_time_with_freeze", "total_time_active_only",
+    "swaps_all", "swaps_with_freeze", "swaps_active_only",
+    "swaps_per_minute_all", "swaps_per_minute_with_freeze", "swaps_per_minute_active_only",
+    "swivel_time_all", "behind_view_time_all",
+    "ping_avg", "ping_max",
+];
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The CSV rows for one replay, sorted by player id so reruns give the same file.
+pub fn export_rows(replay_file: &Path, metadata: &Metadata, results: &PlayerResults) -> Vec<String> {
+    let mut players: Vec<(&PlayerKey, &PlayerResult)> = results.iter().collect();
+    players.sort_by(|a, b| a.0.cmp(b.0));
+    players.iter()
+        .map(|(pid, res)| {
+            [
+                csv_field(&replay_file.display().to_string()),
+                metadata.date.to_string(),
+                csv_field(&metadata.playlist),
+                csv_field(&pid.to_string()),
+                res.ballcam_all.to_string(),
+                res.ballcam_with_freeze.to_string(),
+                res.ballcam_active_only.to_string(),
+                res.total_time_all.to_string(),
+                res.total_time_with_freeze.to_string(),
+                res.total_time_active_only.to_string(),
+                res.swaps_all.to_string(),
+                res.swaps_with_freeze.to_string(),
+                res.swaps_active_only.to_string(),
+                res.swaps_per_minute_all().to_string(),
+                res.swaps_per_minute_with_freeze().to_string(),
+                res.swaps_per_minute_active_only().to_string(),
+                res.swivel.time_all.to_string(),
+                res.behind_view.time_all.to_string(),
+                res.ping.as_ref().map(|ping| ping.avg.to_string()).unwrap_or_default(),
+                res.ping.as_ref().map(|ping| ping.max.to_string()).unwrap_or_default(),
+            ].join(",")
+        })
+        .collect()
+}
+
+/// Write every replay's rows to `export_file`, replacing it.
+pub fn write_export<P: AsRef<Path>>(export_file: P, data: &[(&Path, &Metadata, &PlayerResults)]) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fs::File::create(export_file)?);
+    writeln!(out, "{}", EXPORT_COLUMNS.join(","))?;
+    for (replay_file, metadata, results) in data.iter() {
+        for row in export_rows(replay_file, metadata, results) {
+            writeln!(out, "{}", row)?;
+        }
+    }
+    out.flush()
+}
+
+/// Add one replay's rows to the end of `export_file`, starting it with the header if it's new.
+pub fn append_export<P: AsRef<Path>>(export_file: P, replay_file: &Path, metadata: &Metadata, results: &PlayerResults) -> io::Result<()> {
+    let is_new = fs::metadata(&export_file).map_or(true, |md| md.len() == 0);
+    let mut out = OpenOptions::new().create(true).append(true).open(&export_file)?;
+    let mut contents = String::new();
+    if is_new {
+        contents.push_str(&EXPORT_COLUMNS.join(","));
+        contents.push('\n');
+    }
+    for row in export_rows(replay_file, metadata, results) {
+        contents.push_str(&row);
+        contents.push('\n');
+    }
+    // one write, so a reader never sees half a replay
+    out.write_all(contents.as_bytes())
+}
@@ -0,0 +1,143 @@
+use boxcars::Replay;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::index::find_prop;
+use crate::metrics::{MetricContext, ReplayMetric};
+use crate::player_key::PlayerKey;
+use crate::{GameState, ToggleTimeline};
+
+/// A goal, at the `PostGoalScored` state change. The scoring team and scorer come from the
+/// header's `Goals` list, and are `None` if no entry there matched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Goal {
+    pub time: f32,
+    pub frame: usize,
+    pub team: Option<i32>,
+    pub scorer: Option<String>,
+}
+
+/// Whose goal it was, from a player's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GoalSide {
+    For,
+    Against,
+    /// The scoring team or the player's team isn't known
+    Unknown,
+}
+
+/// A player's camera at the moment of a goal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoalBallcam {
+    pub goal: Goal,
+    pub side: GoalSide,
+    pub ballcam: bool,
+    /// Seconds since the player last toggled ballcam, `None` if they hadn't yet
+    pub since_toggle: Option<f32>,
+}
+
+/// Each player's ballcam state at every goal scored while they were in the game.
+pub struct GoalMetric;
+
+impl ReplayMetric for GoalMetric {
+    type Output = Vec<GoalBallcam>;
+
+    fn compute(&self, ctx: &MetricContext) -> HashMap<PlayerKey, Vec<GoalBallcam>> {
+        let goals = get_goals(ctx);
+        ctx.players.iter()
+            .map(|(pid, idx_list)| {
+                let timeline = ToggleTimeline::ballcam(ctx.lifetimes, ctx.replay, pid, idx_list, ctx.disconnects.get(pid));
+                let camera = &ctx.lifetimes.list[idx_list[0]];
+                let at_goals = goals.iter()
+                    .filter_map(|goal| {
+                        let (ballcam, last_toggle) = timeline.state_at(goal.time)?;
+                        let team = ctx.resolver.team(camera, goal.frame);
+                        let side = match (goal.team, team) {
+                            (Some(scored), Some(team)) if scored == team => GoalSide::For,
+                            (Some(_), Some(_)) => GoalSide::Against,
+                            _ => GoalSide::Unknown,
+                        };
+                        Some(GoalBallcam {
+                            goal: goal.clone(),
+                            side,
+                            ballcam,
+                            since_toggle: last_toggle.map(|toggle_time| goal.time - toggle_time),
+                        })
+                    })
+                    .collect();
+                (pid.clone(), at_goals)
+            })
+            .collect()
+    }
+}
+
+// `(frame, team, scorer)` of each entry in the header's `Goals`
+fn header_goals(replay: &Replay) -> Vec<(usize, i32, String)> {
+    let mut ret: Vec<(usize, i32, String)> = find_prop(&replay.properties, "Goals")
+        .and_then(|p| p.as_array())
+        .map(|goals| goals.iter()
+            .filter_map(|props| Some((
+                find_prop(props, "frame").and_then(|p| p.as_i32())? as usize,
+                find_prop(props, "PlayerTeam").and_then(|p| p.as_i32())?,
+                find_prop(props, "PlayerName").and_then(|p| p.as_string()).unwrap_or("").to_string(),
+            )))
+            .collect())
+        .unwrap_or_default();
+    ret.sort_by_key(|&(frame, _, _)| frame);
+    ret
+}
+
+/// Every goal in the replay, in order. Each `PostGoalScored` change is matched with the latest
+/// header goal at or before it that isn't matched yet.
+pub fn get_goals(ctx: &MetricContext) -> Vec<Goal> {
+    let mut header = header_goals(ctx.replay);
+    ctx.game_states.iter()
+        .filter(|ev| ev.variant == GameState::Goal)
+        .map(|ev| {
+            let matched = header.iter().rposition(|&(frame, _, _)| frame <= ev.info.frame)
+                .map(|ind| header.remove(ind));
+            Goal {
+                time: ev.info.time,
+                frame: ev.info.frame,
+                team: matched.as_ref().map(|(_, team, _)| *team),
+                scorer: matched.map(|(_, _, scorer)| scorer),
+            }
+        })
+        .collect()
+}
+
+/// How often a player had ballcam on at goals, split by side.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GoalSummary {
+    pub goals_for: usize,
+    pub ballcam_for: usize,
+    pub goals_against: usize,
+    pub ballcam_against: usize,
+    /// Mean seconds since the last toggle, over the goals against with a toggle before them
+    pub mean_since_toggle_against: Option<f32>,
+}
+
+impl GoalSummary {
+    pub fn from_goals<'a>(goals: impl IntoIterator<Item = &'a GoalBallcam>) -> Self {
+        let mut ret = Self::default();
+        let mut since_toggle_against: Vec<f32> = Vec::new();
+        for goal in goals {
+            match goal.side {
+                GoalSide::For => {
+                    ret.goals_for += 1;
+                    ret.ballcam_for += goal.ballcam as usize;
+                },
+                GoalSide::Against => {
+                    ret.goals_against += 1;
+                    ret.ballcam_against += goal.ballcam as usize;
+                    since_toggle_against.extend(goal.since_toggle);
+                },
+                GoalSide::Unknown => (),
+            }
+        }
+        if !since_toggle_against.is_empty() {
+            ret.mean_since_toggle_against = Some(since_toggle_against.iter().sum::<f32>() / since_toggle_against.len() as f32);
+        }
+        ret
+    }
+}
@@ -0,0 +1,95 @@
+use boxcars::HeaderProp;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::{get_metadata, parse_rl_header, read_replay_data, Metadata};
+
+/// A player as listed in the replay header's `PlayerStats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexPlayer {
+    pub name: String,
+    pub team: i32,
+    pub platform: Option<String>,
+    pub bot: bool,
+}
+
+/// What the replay header tells us about a match, for finding replays without parsing them fully.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayInfo {
+    pub file: PathBuf,
+    pub metadata: Metadata,
+    /// Full date and time the replay was saved, from the header `Date`
+    pub timestamp: Option<NaiveDateTime>,
+    pub map: Option<String>,
+    /// Number of players on each team, from `PlayerStats`
+    pub team_sizes: (usize, usize),
+    pub score: (i32, i32),
+    pub players: Vec<IndexPlayer>,
+    /// Length of the recording in seconds (`NumFrames / RecordFPS`), including replays and overtime
+    pub length: Option<f32>,
+}
+
+pub(crate) fn find_prop<'a>(props: &'a [(String, HeaderProp)], name: &str) -> Option<&'a HeaderProp> {
+    props.iter().find(|(key, _)| key == name).map(|(_, prop)| prop)
+}
+
+fn parse_timestamp(date: &str) -> Option<NaiveDateTime> {
+    // newer replays use "2023-05-06 21-33-12", older ones "2019-10-01:21-33"
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H-%M-%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d:%H-%M"))
+        .ok()
+}
+
+fn parse_player(props: &[(String, HeaderProp)]) -> IndexPlayer {
+    IndexPlayer {
+        name: find_prop(props, "Name").and_then(|p| p.as_string()).unwrap_or("").to_string(),
+        team: find_prop(props, "Team").and_then(|p| p.as_i32()).unwrap_or(-1),
+        platform: find_prop(props, "Platform").and_then(|p| match p {
+            HeaderProp::Byte { value, .. } => value.as_ref().map(|v| v.trim_start_matches("OnlinePlatform_").to_string()),
+            _ => None,
+        }),
+        bot: find_prop(props, "bBot").and_then(|p| p.as_bool()).unwrap_or(false),
+    }
+}
+
+/// Read a replay's header into a `ReplayInfo`. Doesn't decode the network frames, so this is fast.
+pub fn replay_info<P: AsRef<Path>>(replay_file: P) -> Result<ReplayInfo, ()> {
+    let data = read_replay_data(&replay_file)?;
+    let replay = parse_rl_header(&data).map_err(|e| {
+        eprintln!("\nHIDDEN ERROR:\n{}\n\n", e);
+    })?;
+    let props = &replay.properties;
+
+    let players: Vec<IndexPlayer> = find_prop(props, "PlayerStats")
+        .and_then(|p| p.as_array())
+        .map(|stats| stats.iter().map(|ps| parse_player(ps)).collect())
+        .unwrap_or_default();
+    let num_frames = find_prop(props, "NumFrames").and_then(|p| p.as_i32());
+    let fps = find_prop(props, "RecordFPS").and_then(|p| p.as_float());
+
+    Ok(ReplayInfo {
+        file: replay_file.as_ref().to_path_buf(),
+        metadata: get_metadata(&replay)?,
+        timestamp: find_prop(props, "Date").and_then(|p| p.as_string()).and_then(parse_timestamp),
+        map: find_prop(props, "MapName").and_then(|p| p.as_string()).map(|m| m.to_string()),
+        team_sizes: (
+            players.iter().filter(|p| p.team == 0).count(),
+            players.iter().filter(|p| p.team == 1).count(),
+        ),
+        score: (
+            find_prop(props, "Team0Score").and_then(|p| p.as_i32()).unwrap_or(0),
+            find_prop(props, "Team1Score").and_then(|p| p.as_i32()).unwrap_or(0),
+        ),
+        players,
+        length: num_frames.zip(fps).filter(|&(_, fps)| fps > 0f32).map(|(frames, fps)| frames as f32 / fps),
+    })
+}
+
+impl ReplayInfo {
+    /// `blue1, blue2 vs orange1, orange2`
+    pub fn players_by_team(&self) -> String {
+        let team = |num: i32| self.players.iter().filter(|p| p.team == num).map(|p| p.name.as_str()).collect::<Vec<&str>>().join(", ");
+        format!("{} vs {}", team(0), team(1))
+    }
+}
@@ -3,20 +3,44 @@ use boxcars::{ParseError, Replay, Attribute, HeaderProp};
 use std::cmp::Ordering;
 //use boxcars::{ActorId, Attribute, ObjectId, ParserBuilder, Replay};
 use std::error;
-use std::fs;
 use std::hash::Hash;
 use std::path::{Path, PathBuf};
-//use std::io;
+use std::io::{self, Read};
 use std::collections::{HashMap, HashSet};
 use chrono::{NaiveDate, Duration};
 //use chrono::format::ParseError;
 //
 use plotters::prelude::*;
+use serde::{Deserialize, Serialize};
 //use chrono::{Utc, TimeZone};
 use::dotenv;
 use std::time;
 
 //pub mod ballcam_stats;
+pub mod archive;
+pub mod camera_settings;
+pub mod discovery;
+pub mod dump;
+pub mod dwell;
+pub mod export;
+pub mod goals;
+pub mod index;
+pub mod metrics;
+pub mod person;
+pub mod ping;
+pub mod player_key;
+pub mod reservations;
+pub mod resolver;
+pub mod roster;
+pub mod touches;
+pub mod watch;
+
+use camera_settings::CameraSettings;
+use metrics::{MetricContext, MetricRegistry, MetricResults, ReplayMetric};
+use person::Person;
+use ping::PingStats;
+use player_key::PlayerKey;
+use resolver::PlayerResolver;
 
 fn parse_rl(data: &[u8]) -> Result<Replay, ParseError> {
     boxcars::ParserBuilder::new(data)
@@ -24,7 +48,13 @@ fn parse_rl(data: &[u8]) -> Result<Replay, ParseError> {
         .parse()
 }
 
-fn get_object_id(replay: &Replay, name: &str) -> Option<i32> {
+fn parse_rl_header(data: &[u8]) -> Result<Replay, ParseError> {
+    boxcars::ParserBuilder::new(data)
+        .never_parse_network_data()
+        .parse()
+}
+
+pub fn get_object_id(replay: &Replay, name: &str) -> Option<i32> {
     replay.objects.iter().position(|f| f == name).map(|v| v as i32)
 }
 
@@ -34,8 +64,8 @@ fn get_name_id(replay: &Replay, name: &str) -> Option<i32> {
 }
 */
 
-fn parse_file(filename: &str) -> Result<Replay, Box<dyn error::Error>> {
-    let buffer = fs::read(filename)?;
+pub fn parse_file<P: AsRef<Path>>(filename: P) -> Result<Replay, Box<dyn error::Error>> {
+    let buffer = archive::read_replay(filename)?;
     let replay = parse_rl(&buffer)?;
     Ok(replay)
 }
@@ -57,7 +87,7 @@ impl TimeResult {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metadata {
     pub name: Option<String>,
     pub date: NaiveDate,
@@ -74,6 +104,24 @@ impl Metadata {
     }
 }
 
+/// Which replays to process, decided from the header alone (see `parse_replay_file_filtered`).
+/// The default keeps everything.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayFilter {
+    pub min_date: Option<NaiveDate>,
+    pub max_date: Option<NaiveDate>,
+    /// `Metadata::playlist` values to keep, e.g. `TAGame.Replay_Soccar_TA-2`. Empty keeps every playlist.
+    pub playlists: Vec<String>,
+}
+
+impl ReplayFilter {
+    pub fn matches(&self, md: &Metadata) -> bool {
+        self.min_date.is_none_or(|min_date| md.date >= min_date)
+            && self.max_date.is_none_or(|max_date| md.date <= max_date)
+            && (self.playlists.is_empty() || self.playlists.contains(&md.playlist))
+    }
+}
+
 #[derive(Debug)]
 struct ReplayResult {
     stats: TimeResult,
@@ -86,70 +134,42 @@ impl ReplayResult {
     }
 }
 
-fn get_metadata(replay: &Replay) -> Metadata {
-    //let mut result = Metadata::new();
-    let mut result_name = None;
-    let mut result_date = None;
-    let mut result_mode = None;
+/// The replay's `Metadata` from its header. Fails (with a hidden error) if the header has no
+/// usable `Date` or `TeamSize`.
+fn get_metadata(replay: &Replay) -> Result<Metadata, ()> {
+    let find = |name: &str| replay.properties.iter().find(|prop| prop.0 == name).map(|prop| &prop.1);
+    let missing = |name: &str| eprintln!("\nHIDDEN ERROR:\nNo usable {} in the replay header\n\n", name);
 
-    let player_name_prop = replay.properties
-        .iter()
-        .find(|prop| prop.0 == "PlayerName");
-    if player_name_prop.is_some() {
-        if let HeaderProp::Str(pname) = &player_name_prop.unwrap().1 {
-            result_name = Some(pname.clone());
-        }
-    }
+    let result_name = match find("PlayerName") {
+        Some(HeaderProp::Str(pname)) => Some(pname.clone()),
+        _ => None,
+    };
 
-    let game_time_prop = replay.properties
-        .iter()
-        .find(|prop| prop.0 == "Date").unwrap();
-    if let HeaderProp::Str(gdate) = &game_time_prop.1 {
-        result_date = gdate.split_whitespace().next().map(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok()).flatten();
-    }
+    let result_date = match find("Date") {
+        // "2023-05-06 21-33-12", or "2019-10-01:21-33" in older replays
+        Some(HeaderProp::Str(gdate)) => gdate.get(..10).and_then(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok()),
+        _ => None,
+    }.ok_or_else(|| missing("Date"))?;
 
-    let playlist_prop = replay.properties
-        .iter()
-        .find(|prop| prop.0 == "TeamSize").unwrap();
-    if let HeaderProp::Int(tsize) = &playlist_prop.1 {
-        result_mode = Some(format!("{}-{}", replay.game_type, tsize));
-    }
+    let result_mode = match find("TeamSize") {
+        Some(HeaderProp::Int(tsize)) => Some(format!("{}-{}", replay.game_type, tsize)),
+        _ => None,
+    }.ok_or_else(|| missing("TeamSize"))?;
 
-    Metadata::new(result_name, result_date.unwrap(), result_mode.unwrap())
+    Ok(Metadata::new(result_name, result_date, result_mode))
 }
 
-pub fn get_replay_list(dir: &str) -> Vec<String> {
-    let path = Path::new(dir);
-    fs::read_dir(path)
-        .expect("Unable to list")
-        .into_iter()
-        .filter(|r| r.is_ok())
-        .map(|r| r.unwrap().path())
-        .filter(|r| r.extension().map_or(false, |ex| ex == "replay"))
-        .map(|p| p.into_os_string().into_string().expect("failed to convert PathBuf to String"))
-        .collect()
-}
-
-fn fix_bytes(b: u64) -> String {
-    let big_endian = b.to_be_bytes().map(|b| format!("{:02x}", b)).join("");
-    let little_endian = b.to_le_bytes().map(|b| format!("{:02x}", b)).join("");
-    little_endian
+/// The `.replay` files directly in `dir`. See `discovery::ReplaySource` for more ways to find replays.
+pub fn get_replay_list<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
+    discovery::ReplaySource::new().root(dir).discover()
 }
 
-pub fn uid_to_string(uid: &UniqueId) -> String {
-    match &uid.remote_id {
-        boxcars::RemoteId::QQ(rid) => format!("qq-{}-{}", rid, uid.local_id),
-        boxcars::RemoteId::Xbox(rid) => format!("xbox-{}-{}", fix_bytes(*rid), uid.local_id),
-        boxcars::RemoteId::Epic(rid) => format!("epic-{}-{}", rid, uid.local_id),
-        boxcars::RemoteId::Steam(rid) => format!("steam-{}-{}", rid, uid.local_id),
-        boxcars::RemoteId::PsyNet(psy_id) => format!("psynet-{}-{}", fix_bytes(psy_id.online_id), uid.local_id),
-        boxcars::RemoteId::Switch(switch_id) => format!("switch-{}-{}", switch_id.online_id, uid.local_id),
-        boxcars::RemoteId::PlayStation(psn_id) => format!("ps4-{}-{}", psn_id.name, uid.local_id),
-        boxcars::RemoteId::SplitScreen(split_id) => format!("splitscreen-{}-{}", split_id, uid.local_id),
+pub fn plot_updated(data: Vec<(Metadata, PlayerResults)>, file: &str, target: &Person, plot_others: bool) {
+    // nothing to draw (e.g. no games in this playlist yet), leave any older plot alone
+    if !data.iter().any(|(_, hm)| target.played_in(hm)) {
+        eprintln!("No replays with {} for {}, skipping it", target.name, file);
+        return;
     }
-}
-
-pub fn plot_updated(data: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)>, file: &str, target_player: &str, plot_others: bool) {
     let fname = format!("outputs/{}.png", file);
     let root_area = BitMapBackend::new(&fname, (600*2, 2*400))
         .into_drawing_area();
@@ -173,10 +193,11 @@ pub fn plot_updated(data: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)>, file
     data.iter().for_each(|(md, hm)| {
         let mut other_top = 0f32;
         let mut other_bot = 0f32;
+        if let Some(val) = target.result_in(hm) {
+            self_series.push((md.date, 100f32 * val.ballcam_active_only / val.total_time_active_only));
+        }
         for (key, val) in hm {
-            if uid_to_string(key) == target_player {
-                self_series.push((md.date, 100f32 * val.ballcam_active_only / val.total_time_active_only));
-            } else {
+            if !target.owns(key) {
                 other_top += val.ballcam_active_only;
                 other_bot += val.total_time_active_only;
             }
@@ -217,6 +238,22 @@ pub fn plot_updated(data: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)>, file
 
     ctx.configure_mesh().draw().unwrap();
 
+    // mark the dates where the target changed their camera settings, on any of their accounts
+    // each account on its own, a console and a PC account can have different settings
+    let camera_history = camera_settings::camera_history(&data);
+    let mut camera_changes: Vec<NaiveDate> = target.accounts.iter()
+        .filter_map(|acc| camera_history.get(acc))
+        .flat_map(|history| camera_settings::camera_changes(history))
+        .map(|cc| cc.date)
+        .collect();
+    camera_changes.sort();
+    camera_changes.dedup();
+    for change_date in camera_changes {
+        ctx.draw_series(
+            LineSeries::new(vec![(change_date, min_val), (change_date, max_val)], &GREEN,)
+        ).unwrap();
+    }
+
     ctx.draw_series(
         LineSeries::new(self_series.clone(), &BLUE,)
     ).unwrap();
@@ -258,6 +295,123 @@ pub fn plot_updated(data: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)>, file
 
 }
 
+/// A person's active time ballcam % in each replay they played.
+fn person_series(data: &[(Metadata, PlayerResults)], person: &Person) -> Vec<(NaiveDate, f32)> {
+    data.iter()
+        .filter_map(|(md, hm)| person.result_in(hm).map(|res| (md.date, 100f32 * res.ballcam_active_only / res.total_time_active_only)))
+        .collect()
+}
+
+/// Like `plot_updated`, but with one line per person, in different colors with a legend.
+pub fn plot_overlay(data: &[(Metadata, PlayerResults)], file: &str, people: &[Person]) {
+    let series: Vec<(&Person, Vec<(NaiveDate, f32)>)> = people.iter()
+        .map(|person| (person, person_series(data, person)))
+        .filter(|(_, ser)| !ser.is_empty())
+        .collect();
+    if series.is_empty() {
+        eprintln!("No replays with any of the players for {}, skipping it", file);
+        return;
+    }
+
+    let fname = format!("outputs/{}.png", file);
+    let root_area = BitMapBackend::new(&fname, (600*2, 2*400))
+        .into_drawing_area();
+    root_area.fill(&WHITE).unwrap();
+
+    let min_val: f32 = 0f32;
+    let max_val: f32 = 100f32;
+    let start_date = series.iter().map(|(_, ser)| ser[0].0).min().unwrap();
+    let end_date = series.iter().map(|(_, ser)| ser[ser.len() - 1].0).max().unwrap()
+        + Duration::days(14);
+
+    let mut ctx = ChartBuilder::on(&root_area)
+        .set_label_area_size(LabelAreaPosition::Left, 40)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .caption(format!("% ballcam - {}", file), ("sans-serif", 40))
+        .build_cartesian_2d(start_date..end_date, min_val..max_val)
+        .unwrap();
+
+    ctx.configure_mesh().draw().unwrap();
+
+    for (ind, (person, ser)) in series.iter().enumerate() {
+        let color = Palette99::pick(ind).to_rgba();
+        ctx.draw_series(
+            LineSeries::new(ser.clone(), &color,)
+        ).unwrap()
+            .label(person.name.as_str())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+
+        ctx.draw_series(
+            ser.iter()
+                .map(|&(dt, vv)| Circle::new((dt, vv), 3, color.filled())),
+        ).unwrap();
+    }
+
+    ctx.configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .unwrap();
+}
+
+/// Ballcam % on top and swaps per minute (active time only) below it, both over time, to `outputs/{file}-swaps.png`.
+pub fn plot_swap_rate(data: &[(Metadata, PlayerResults)], file: &str, target: &Person) {
+    let results: Vec<(NaiveDate, PlayerResult)> = data.iter()
+        .filter_map(|(md, hm)| target.result_in(hm).map(|res| (md.date, res)))
+        .collect();
+    if results.is_empty() {
+        eprintln!("No replays with {} for {}, skipping it", target.name, file);
+        return;
+    }
+    let ballcam_series: Vec<(NaiveDate, f32)> = results.iter()
+        .map(|(date, res)| (*date, 100f32 * res.ballcam_active_only / res.total_time_active_only))
+        .collect();
+    let swap_series: Vec<(NaiveDate, f32)> = results.iter()
+        .map(|(date, res)| (*date, res.swaps_per_minute_active_only()))
+        .collect();
+
+    let fname = format!("outputs/{}-swaps.png", file);
+    let root_area = BitMapBackend::new(&fname, (600*2, 2*400))
+        .into_drawing_area();
+    root_area.fill(&WHITE).unwrap();
+    let (top_area, bottom_area) = root_area.split_vertically(400);
+
+    let start_date = results[0].0;
+    let end_date = results[results.len() - 1].0 + Duration::days(14);
+    let max_rate = swap_series.iter().map(|(_, rate)| *rate).fold(0f32, f32::max).max(1f32) * 1.1;
+
+    for (area, series, caption, max_val, color) in [
+        (&top_area, &ballcam_series, format!("% ballcam - {}", file), 100f32, BLUE),
+        (&bottom_area, &swap_series, format!("swaps per minute - {}", file), max_rate, RED),
+    ] {
+        let mut ctx = ChartBuilder::on(area)
+            .set_label_area_size(LabelAreaPosition::Left, 40)
+            .set_label_area_size(LabelAreaPosition::Bottom, 40)
+            .caption(caption, ("sans-serif", 30))
+            .build_cartesian_2d(start_date..end_date, 0f32..max_val)
+            .unwrap();
+
+        ctx.configure_mesh().draw().unwrap();
+
+        ctx.draw_series(
+            LineSeries::new(series.clone(), &color,)
+        ).unwrap();
+
+        ctx.draw_series(
+            series.iter()
+                .map(|&(dt, vv)| Circle::new((dt, vv), 3, color.filled())),
+        ).unwrap();
+    }
+}
+
+/// `plot_overlay` for single accounts, labelled with their keys.
+pub fn plot_players(data: &[(Metadata, PlayerResults)], file: &str, keys: &[PlayerKey]) {
+    let people: Vec<Person> = keys.iter()
+        .map(|key| Person::new(&key.to_string(), vec![key.clone()]))
+        .collect();
+    plot_overlay(data, file, &people);
+}
+
 fn get_prop_string(replay: &Replay, prop: &str) -> Option<String> {
     let found = replay.properties.iter().find(|&p| {
         p.0 == prop
@@ -278,15 +432,17 @@ fn get_prop_string(replay: &Replay, prop: &str) -> Option<String> {
 }
 
 
-#[derive(Debug)]
-enum ChangeEvent {
-    N(NewActor),
+/// A single network event for an actor: created (`N`), deleted (`D`) or attribute updated (`U`).
+/// Borrows from the replay's network frames, so building lifetimes doesn't copy the whole stream.
+#[derive(Debug, Clone, Copy)]
+pub enum ChangeEvent<'a> {
+    N(&'a NewActor),
     D(ActorId),
-    U(UpdatedAttribute),
+    U(&'a UpdatedAttribute),
 }
 
-impl ChangeEvent {
-    fn actor_id(&self) -> i32 {
+impl ChangeEvent<'_> {
+    pub fn actor_id(&self) -> i32 {
         match self {
             Self::N(na) => na.actor_id.0,
             Self::D(da) => da.0,
@@ -294,7 +450,8 @@ impl ChangeEvent {
         }
     }
 
-    fn object_id(&self) -> Option<i32> {
+    /// The object the actor was created from for `N`, the attribute's object for `U`.
+    pub fn object_id(&self) -> Option<i32> {
         match self {
             Self::N(na) => Some(na.object_id.0),
             Self::D(da) => None,
@@ -304,14 +461,14 @@ impl ChangeEvent {
 }
 
 #[derive(Debug)]
-struct Event {
-    event: ChangeEvent,
-    frame: usize,
-    time: f32,
+pub struct Event<'a> {
+    pub event: ChangeEvent<'a>,
+    pub frame: usize,
+    pub time: f32,
 }
 
-impl Event {
-    fn from(event: ChangeEvent, frame: usize, time: f32) -> Self {
+impl<'a> Event<'a> {
+    fn from(event: ChangeEvent<'a>, frame: usize, time: f32) -> Self {
         Self {
             event,
             frame,
@@ -320,43 +477,113 @@ impl Event {
     }
 }
 
+/// Every event for an actor id, from its creation until it's deleted or the id gets reused.
+/// Never empty.
 #[derive(Debug)]
-struct Lifetime {
-    events: Vec<Event>,
+pub struct Lifetime<'a> {
+    pub events: Vec<Event<'a>>,
 }
 
-impl Lifetime {
-    fn from(v: Vec<Event>) -> Self {
+impl<'a> Lifetime<'a> {
+    fn from(v: Vec<Event<'a>>) -> Self {
         Self {
             events: v,
         }
     }
+
+    pub fn actor_id(&self) -> i32 {
+        self.events[0].event.actor_id()
+    }
+
+    /// The object the actor was created from, if we saw it being created.
+    pub fn object_id(&self) -> Option<i32> {
+        match &self.events[0].event {
+            ChangeEvent::N(na) => Some(na.object_id.0),
+            _ => None,
+        }
+    }
+
+    pub fn start_frame(&self) -> usize {
+        self.events[0].frame
+    }
+
+    pub fn end_frame(&self) -> usize {
+        self.events[self.events.len() - 1].frame
+    }
+
+    /// Every update of the attribute with the given object id, in order.
+    pub fn attribute_updates(&self, object_id: i32) -> impl Iterator<Item = (&Event<'a>, &'a Attribute)> {
+        self.events.iter().filter_map(move |ev| match &ev.event {
+            ChangeEvent::U(ua) if ua.object_id.0 == object_id => Some((ev, &ua.attribute)),
+            _ => None,
+        })
+    }
+
+    /// Every `(frame, time, value)` update of a property (e.g. `Engine.PlayerReplicationInfo:Ping`)
+    /// in this lifetime, in order. Actor ids get reused, so this only covers this one actor.
+    pub fn attribute_timeline(&self, replay: &Replay, property: &str) -> Vec<(usize, f32, &'a Attribute)> {
+        match get_object_id(replay, property) {
+            Some(property_id) => self.attribute_updates(property_id)
+                .map(|(ev, attr)| (ev.frame, ev.time, attr))
+                .collect(),
+            None => vec![],
+        }
+    }
 }
 
+/// All the actor lifetimes of a replay, indexed by actor id. Build one with `parse_lifetimes`.
 #[derive(Debug)]
-struct LifetimeList {
-    list: Vec<Lifetime>,
+pub struct LifetimeList<'a> {
+    list: Vec<Lifetime<'a>>,
     actor_map: HashMap<i32, Vec<usize>>,
 }
 
-impl LifetimeList {
-    fn from(list: Vec<Lifetime>) -> Self {
+impl<'a> LifetimeList<'a> {
+    fn from(list: Vec<Lifetime<'a>>) -> Self {
         Self {
             actor_map: bucket_index(&list, |lt| {Some(lt.events[0].event.actor_id())}),
             list,
         }
     }
 
-    fn lookup_actor(&self, actor_id: i32, frame_id: usize) -> Result<&Lifetime, ()> {
+    pub fn lifetimes(&self) -> &[Lifetime<'a>] {
+        &self.list
+    }
+
+    fn lookup_index(&self, actor_id: i32, frame_id: usize) -> Option<usize> {
         //dbg!(actor_id, frame_id);
-        self.actor_map.get(&actor_id).map(|vv| {
+        self.actor_map.get(&actor_id).and_then(|vv| {
             //dbg!(&vv.iter().map(|&vx| self.list[vx].events[0].frame).collect::<Vec<usize>>());
             let rind = vv.partition_point(|&lt| {
                 self.list[lt].events[0].frame <= frame_id
             });
-            //&self.list[vv[rind.min(vv.len()-1)]] // TODO we got an out of bounds read here...
-            &self.list[vv[rind-1]]
-        }).ok_or(())
+            // no lifetime for this actor had started yet
+            rind.checked_sub(1).map(|ind| vv[ind])
+        })
+    }
+
+    /// The lifetime of an actor id at a frame, i.e. the last one that started at or before it.
+    pub fn actor_at(&self, actor_id: i32, frame: usize) -> Option<&Lifetime<'a>> {
+        self.lookup_index(actor_id, frame).map(|ind| &self.list[ind])
+    }
+
+    /// Follow an actor reference attribute (e.g. `TAGame.CameraSettingsActor_TA:PRI`) to the
+    /// lifetime it pointed to at a frame. `None` if it isn't a reference or points at nothing.
+    pub fn resolve_actor_reference(&self, attribute: &Attribute, frame: usize) -> Option<&Lifetime<'a>> {
+        parse_actor_reference(attribute).ok().and_then(|actor_id| self.actor_at(actor_id, frame))
+    }
+
+    /// Every lifetime of actors created from the object with this name, e.g. `TAGame.Default__CameraSettingsActor_TA`.
+    pub fn lifetimes_of_object(&self, replay: &Replay, name: &str) -> Vec<&Lifetime<'a>> {
+        match get_object_id(replay, name) {
+            Some(object_id) => self.list.iter().filter(|lt| lt.object_id() == Some(object_id)).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Group lifetime indexes (into `lifetimes()`) by whatever key `func` returns. Lifetimes where it returns `None` are left out.
+    pub fn bucket_by<T: Hash+Eq, F: Fn(&Lifetime) -> Option<T>>(&self, func: F) -> HashMap<T, Vec<usize>> {
+        bucket_index(&self.list, func)
     }
 }
 
@@ -375,9 +602,14 @@ fn bucket_index<T: Hash+Eq, F: Fn(&Lifetime) -> Option<T>>(v: &Vec<Lifetime>, fu
 
 trait DumpEvent {
     fn dump(&self, replay: &Replay) -> String;
+    fn dump_json(&self, replay: &Replay) -> serde_json::Value;
+}
+
+fn object_name(replay: &Replay, object_id: i32) -> &str {
+    replay.objects.get(object_id as usize).map(|s| s.as_str()).unwrap_or("N/A")
 }
 
-impl DumpEvent for Event {
+impl DumpEvent for Event<'_> {
     fn dump(&self, replay: &Replay) -> String {
         vec![
             format!("FrameID:           {}", self.frame),
@@ -385,9 +617,16 @@ impl DumpEvent for Event {
             self.event.dump(&replay),
         ].join("\n")
     }
+
+    fn dump_json(&self, replay: &Replay) -> serde_json::Value {
+        let mut ret = self.event.dump_json(replay);
+        ret["frame"] = self.frame.into();
+        ret["time"] = self.time.into();
+        ret
+    }
 }
 
-impl DumpEvent for ChangeEvent {
+impl DumpEvent for ChangeEvent<'_> {
     fn dump(&self, replay: &Replay) -> String {
         match self {
             ChangeEvent::N(na) => na.dump(&replay),
@@ -395,6 +634,14 @@ impl DumpEvent for ChangeEvent {
             ChangeEvent::U(ua) => ua.dump(&replay),
         }
     }
+
+    fn dump_json(&self, replay: &Replay) -> serde_json::Value {
+        match self {
+            ChangeEvent::N(na) => na.dump_json(replay),
+            ChangeEvent::D(da) => da.dump_json(replay),
+            ChangeEvent::U(ua) => ua.dump_json(replay),
+        }
+    }
 }
 
 impl DumpEvent for UpdatedAttribute {
@@ -407,6 +654,16 @@ impl DumpEvent for UpdatedAttribute {
             format!("Attribute:\n{:?}", self.attribute),
         ].join("\n")
     }
+
+    fn dump_json(&self, replay: &Replay) -> serde_json::Value {
+        serde_json::json!({
+            "type": "updated",
+            "actor_id": self.actor_id.0,
+            "object": object_name(replay, self.object_id.0),
+            "stream_id": self.stream_id.0,
+            "attribute": serde_json::to_value(&self.attribute).unwrap_or_default(),
+        })
+    }
 }
 
 impl DumpEvent for NewActor {
@@ -419,6 +676,16 @@ impl DumpEvent for NewActor {
             format!("Initial Trajectory:\n{:?}", self.initial_trajectory),
         ].join("\n")
     }
+
+    fn dump_json(&self, replay: &Replay) -> serde_json::Value {
+        serde_json::json!({
+            "type": "new",
+            "actor_id": self.actor_id.0,
+            "object": object_name(replay, self.object_id.0),
+            "name": self.name_id.and_then(|nid| replay.names.get(nid as usize)),
+            "initial_trajectory": serde_json::to_value(self.initial_trajectory).unwrap_or_default(),
+        })
+    }
 }
 
 impl DumpEvent for ActorId {
@@ -428,10 +695,18 @@ impl DumpEvent for ActorId {
             format!("Deleted Actor ID: {}", self.0),
         ].join("\n")
     }
+
+    fn dump_json(&self, _replay: &Replay) -> serde_json::Value {
+        serde_json::json!({
+            "type": "deleted",
+            "actor_id": self.0,
+        })
+    }
 }
 
 
-fn parse_lifetimes(replay: &Replay) -> LifetimeList {
+/// Walk the network frames and split every actor's events into lifetimes.
+pub fn parse_lifetimes(replay: &Replay) -> LifetimeList<'_> {
     let mut active_lifetimes: HashMap<i32, Vec<Event>> = HashMap::new();
     let mut ret: Vec<Lifetime> = vec![];
 
@@ -446,13 +721,13 @@ fn parse_lifetimes(replay: &Replay) -> LifetimeList {
                 }
 
                 // insert the new create event to a new active entry
-                active_lifetimes.insert(na.actor_id.0, vec![Event::from(ChangeEvent::N(na.clone()), frame_id, fr.time)]);
+                active_lifetimes.insert(na.actor_id.0, vec![Event::from(ChangeEvent::N(na), frame_id, fr.time)]);
             });
 
             fr.deleted_actors.iter().for_each(|da| {
                 // Append delete event to active entry
                 let entry = active_lifetimes.entry(da.0).or_insert(vec![]);
-                entry.push(Event::from(ChangeEvent::D(da.clone()), frame_id, fr.time));
+                entry.push(Event::from(ChangeEvent::D(*da), frame_id, fr.time));
 
                 // flush deleted active entry to return lifetime list
                 let old_lifetime = active_lifetimes.remove(&da.0);
@@ -464,7 +739,7 @@ fn parse_lifetimes(replay: &Replay) -> LifetimeList {
             fr.updated_actors.iter().for_each(|ua| {
                 // append update event to active entry
                 let entry = active_lifetimes.entry(ua.actor_id.0).or_insert(vec![]);
-                entry.push(Event::from(ChangeEvent::U(ua.clone()), frame_id, fr.time));
+                entry.push(Event::from(ChangeEvent::U(ua), frame_id, fr.time));
             });
         });
 
@@ -487,56 +762,25 @@ fn parse_actor_reference(atr: &Attribute) -> Result<i32, ()> {
     }
 }
 
-fn player_id_buckets(ltl: &LifetimeList, replay: &Replay) -> HashMap<UniqueId, Vec<usize>> {
+fn player_id_buckets(ltl: &LifetimeList, replay: &Replay) -> HashMap<PlayerKey, Vec<usize>> {
     let camera_create   = get_object_id(replay, "TAGame.Default__CameraSettingsActor_TA").unwrap();
-    let cam_to_pri      = get_object_id(replay, "TAGame.CameraSettingsActor_TA:PRI").unwrap();
-    let pri_to_unique   = get_object_id(replay, "Engine.PlayerReplicationInfo:UniqueId").unwrap();
+    let resolver = PlayerResolver::new(ltl, replay);
 
-    let player_history: HashMap<UniqueId, Vec<usize>> = bucket_index(&ltl.list, |lt| {
-        if lt.events[0].event.object_id() != Some(camera_create) {
+    let player_history: HashMap<PlayerKey, Vec<usize>> = bucket_index(&ltl.list, |lt| {
+        if lt.object_id() != Some(camera_create) {
             return None;
         }
-
-        let pri_attr = lt.events.iter().find_map(|cvt| {
-            if cvt.event.object_id() == Some(cam_to_pri) {
-                if let ChangeEvent::U(update_event) = &cvt.event {
-                    return Some((cvt.frame, &update_event.attribute));
-                }
-            }
-            return None;
-        });
-
-        if let Some((ref_frame_id, pri_ref)) = pri_attr {
-            if let Ok(pri_actor) = parse_actor_reference(pri_ref) {
-                let pri_lifetime = ltl.lookup_actor(pri_actor, ref_frame_id).expect("ACTOR ASSOC DIDN'T EXIST. PAIN");
-
-                let unique_atr = pri_lifetime.events.iter().find_map(|pvt| {
-                    if pvt.event.object_id() == Some(pri_to_unique) {
-                        if let ChangeEvent::U(update_event) = &pvt.event {
-                            return Some(&update_event.attribute);
-                        }
-                    }
-                    return None;
-                });
-
-                if let Some(uniq_atr) = unique_atr {
-                    if let Attribute::UniqueId(uid) = uniq_atr {
-                        return Some(*uid.to_owned());
-                    }
-                }
-            }
-        }
-        return None;
+        resolver.resolve_lifetime(lt, lt.start_frame()).map(|uid| PlayerKey::from(&uid))
     });
 
     player_history
 }
 
 
-fn get_disconnect_players(ltl: &LifetimeList, replay: &Replay) -> HashMap<UniqueId, f32> {
+fn get_disconnect_players(ltl: &LifetimeList, replay: &Replay) -> HashMap<PlayerKey, f32> {
     let reservations = get_object_id(replay, "ProjectX.GRI_X:Reservations").unwrap();
 
-    let mut ret: HashMap<UniqueId, f32> = HashMap::new();
+    let mut ret: HashMap<PlayerKey, f32> = HashMap::new();
 
     let target_object_id = replay.objects.iter().position(|obj| obj.ends_with(":GameReplicationInfoArchetype")).map(|ind| ind as i32);
 
@@ -557,7 +801,7 @@ fn get_disconnect_players(ltl: &LifetimeList, replay: &Replay) -> HashMap<Unique
             if let ChangeEvent::U(ua) = &ff.event {
                 if ua.object_id.0 == reservations {
                     if let Attribute::Reservation(trev) = &ua.attribute {
-                        if !ret.contains_key(&trev.unique_id)
+                        if !ret.contains_key(&PlayerKey::from(&trev.unique_id))
                                 && res_changes.contains_key(&trev.unique_id)
                                 && !(trev.unknown1 && trev.unknown2)
                                 && (res_changes.get(&trev.unique_id).unwrap().0     // weren't already
@@ -573,7 +817,7 @@ fn get_disconnect_players(ltl: &LifetimeList, replay: &Replay) -> HashMap<Unique
                             eprintln!("  u1: {}", trev.unknown1);
                             eprintln!("  u2: {}", trev.unknown2);
                             // */
-                            ret.insert(trev.unique_id.clone(), ff.time);
+                            ret.insert(PlayerKey::from(&trev.unique_id), ff.time);
                         }
                         res_changes.insert(trev.unique_id.clone(), (trev.unknown1, trev.unknown2));
 
@@ -603,45 +847,23 @@ fn get_disconnect_players(ltl: &LifetimeList, replay: &Replay) -> HashMap<Unique
 }
 
 
-fn get_ping_from_cam(index: usize, ltl: &LifetimeList, replay: &Replay) -> Option<(f32, usize)> {
-    let cam_to_pri      = replay.objects.iter().position(|pp| pp == "TAGame.CameraSettingsActor_TA:PRI").unwrap();
-    let ping_object = replay.objects.iter().position(|pp| pp == "Engine.PlayerReplicationInfo:Ping").unwrap();
-    let pri_attr = ltl.list[index].events.iter().find_map(|cvt| {
-        match &cvt.event {
-            ChangeEvent::U(ua) => {
-                if ua.object_id.0 as usize == cam_to_pri {
-                    Some((cvt.frame, &ua.attribute))
-                } else {
-                    None
-                }
-            },
-            _ => None,
-        }
-    });
-    if let Some((ref_frame_id, pri_ref)) = pri_attr {
-        if let Ok(pri_actor) = parse_actor_reference(pri_ref) {
-            let pri_lifetime = ltl.lookup_actor(pri_actor, ref_frame_id).expect("ACTOR ASSOC DIDN'T EXIST. PAIN");
-            let ping_raw = pri_lifetime.events.iter().find_map(|pvt| {
-                match &pvt.event {
-                    ChangeEvent::U(ua) => {
-                        if ua.object_id.0 as usize == ping_object {
-                            Some((pvt.time, &ua.attribute))
-                        } else {
-                            None
-                        }
-                    },
+fn get_pings_from_cam(index: usize, ltl: &LifetimeList, replay: &Replay) -> Vec<(f32, u8)> {
+    let ping_object = match get_object_id(replay, "Engine.PlayerReplicationInfo:Ping") {
+        Some(ping_object) => ping_object,
+        None => return vec![],
+    };
+    let cam_lifetime = &ltl.list[index];
+    PlayerResolver::new(ltl, replay)
+        .resolve_pri(cam_lifetime, cam_lifetime.start_frame())
+        .map(|pri_lifetime| {
+            pri_lifetime.attribute_updates(ping_object)
+                .filter_map(|(pvt, attr)| match attr {
+                    Attribute::Byte(ping_val) => Some((pvt.time, *ping_val)),
                     _ => None,
-                }
-            });
-            if let Some((ping_time, ping_unwrapped)) = ping_raw {
-                //dbg!(&ping_unwrapped);
-                if let Attribute::Byte(ping_val) = ping_unwrapped {
-                    return Some((ping_time, *ping_val as usize)); //parse_id(uniq_atr).ok();
-                }
-            }
-        }
-    }
-    return None;
+                })
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 /*
@@ -661,25 +883,25 @@ impl BallcamResults {
 }
 */
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BallcamResults {
     //results: HashMap<String, (f32, i32)>,
-    pub results: HashMap<UniqueId, (f32, i32)>,
+    pub results: HashMap<PlayerKey, (f32, i32)>,
 }
 
 impl BallcamResults {
     //fn from(results: HashMap<String, (f32, i32)>) -> Self {
-    fn from(results: HashMap<UniqueId, (f32, i32)>) -> Self {
+    fn from(results: HashMap<PlayerKey, (f32, i32)>) -> Self {
         Self {
             results,
         }
     }
 }
 
-#[derive(Debug)]
-struct FrameInfo {
-    time: f32,
-    frame: usize,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FrameInfo {
+    pub time: f32,
+    pub frame: usize,
 }
 
 impl FrameInfo {
@@ -691,17 +913,19 @@ impl FrameInfo {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum GameState {
+/// `TAGame.GameEvent_TA:ReplicatedStateName` values we care about.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum GameState {
+    /// `PostGoalScored`
     Goal,
     Active,
     Countdown,
 }
 
-#[derive(Debug)]
-struct GameStateEvent {
-    variant: GameState,
-    info: FrameInfo,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GameStateEvent {
+    pub variant: GameState,
+    pub info: FrameInfo,
 }
 
 impl GameStateEvent {
@@ -811,7 +1035,10 @@ fn get_state_changes(ltl: &LifetimeList, replay: &Replay) -> Vec<GameStateEvent>
     real_state_changes
 }
 
-#[derive(Debug, Clone)]
+/// Each player's `PlayerResult` for one replay.
+pub type PlayerResults = HashMap<PlayerKey, PlayerResult>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayerResult {
     pub swaps_all: i32,
     pub swaps_with_freeze: i32,
@@ -822,9 +1049,34 @@ pub struct PlayerResult {
     pub total_time_all: f32,
     pub total_time_with_freeze: f32,
     pub total_time_active_only: f32,
+    pub camera_settings: Option<CameraSettings>,
+    pub swivel: ToggleResult,
+    pub behind_view: ToggleResult,
+    pub ping: Option<PingStats>,
+}
+
+// toggles per minute of `time` seconds, 0 if there was no time
+fn per_minute(toggles: i32, time: f32) -> f32 {
+    if time > 0f32 {
+        toggles as f32 / (time / 60f32)
+    } else {
+        0f32
+    }
 }
 
 impl PlayerResult {
+    pub fn swaps_per_minute_all(&self) -> f32 {
+        per_minute(self.swaps_all, self.total_time_all)
+    }
+
+    pub fn swaps_per_minute_with_freeze(&self) -> f32 {
+        per_minute(self.swaps_with_freeze, self.total_time_with_freeze)
+    }
+
+    pub fn swaps_per_minute_active_only(&self) -> f32 {
+        per_minute(self.swaps_active_only, self.total_time_active_only)
+    }
+
     fn new() -> Self {
         Self {
             swaps_all: 0,
@@ -836,6 +1088,10 @@ impl PlayerResult {
             total_time_all: 0f32,
             total_time_with_freeze: 0f32,
             total_time_active_only: 0f32,
+            camera_settings: None,
+            swivel: ToggleResult::new(),
+            behind_view: ToggleResult::new(),
+            ping: None,
         }
     }
 
@@ -878,6 +1134,52 @@ impl PlayerResult {
     }
 }
 
+/// Time spent in, and number of toggles of, a camera mode other than ballcam
+/// (e.g. swivel or rear view), with the same breakdown as `PlayerResult`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToggleResult {
+    pub toggles_all: i32,
+    pub toggles_with_freeze: i32,
+    pub toggles_active_only: i32,
+    pub time_all: f32,
+    pub time_with_freeze: f32,
+    pub time_active_only: f32,
+}
+
+impl ToggleResult {
+    pub(crate) fn add(&mut self, other: &ToggleResult) {
+        self.toggles_all += other.toggles_all;
+        self.toggles_with_freeze += other.toggles_with_freeze;
+        self.toggles_active_only += other.toggles_active_only;
+        self.time_all += other.time_all;
+        self.time_with_freeze += other.time_with_freeze;
+        self.time_active_only += other.time_active_only;
+    }
+
+    fn new() -> Self {
+        Self {
+            toggles_all: 0,
+            toggles_with_freeze: 0,
+            toggles_active_only: 0,
+            time_all: 0f32,
+            time_with_freeze: 0f32,
+            time_active_only: 0f32,
+        }
+    }
+
+    // process_ballcam works for any camera toggle, so just take the "ballcam" numbers from its result
+    fn from(res: &PlayerResult) -> Self {
+        Self {
+            toggles_all: res.swaps_all,
+            toggles_with_freeze: res.swaps_with_freeze,
+            toggles_active_only: res.swaps_active_only,
+            time_all: res.ballcam_all,
+            time_with_freeze: res.ballcam_with_freeze,
+            time_active_only: res.ballcam_active_only,
+        }
+    }
+}
+
 /*
 fn get_no_replay_time(new_time: f32, new_state: f32, last_state: Option<bool>, last_time: Option<f32>, game_state_change: &Vec<(f32, bool)>) -> (f32, bool) {
     let current_state = game_state_change.partition_point(|ee| {ee.0 <= new_time}) - 1;
@@ -923,11 +1225,15 @@ impl PartialOrd<GameStateEvent> for BallcamEvent {
 }
 */
 
+const BALLCAM_OBJECT: &str = "TAGame.CameraSettingsActor_TA:bUsingSecondaryCamera";
+const SWIVEL_OBJECT: &str = "TAGame.CameraSettingsActor_TA:bUsingSwivel";
+const BEHIND_VIEW_OBJECT: &str = "TAGame.CameraSettingsActor_TA:bUsingBehindView";
+
 //fn get_ballcam_list(ltl: &LifetimeList, replay: &Replay, player_buckets: &HashMap<UniqueId, &Vec<usize>>) -> HashMap<UniqueId, Vec<BallcamEvent>> {
-fn get_ballcam_list(ltl: &LifetimeList, replay: &Replay, pid: &UniqueId, idx_list: &Vec<usize>, disconnect_time: Option<&f32>) -> Vec<BallcamEvent> {
+fn get_ballcam_list(ltl: &LifetimeList, replay: &Replay, pid: &PlayerKey, idx_list: &Vec<usize>, disconnect_time: Option<&f32>, toggle_object: &str) -> Vec<BallcamEvent> {
     let mut ret: Vec<BallcamEvent> = Vec::new();
     
-    let ballcam_id = replay.objects.iter().position(|pp| pp == "TAGame.CameraSettingsActor_TA:bUsingSecondaryCamera").unwrap() as i32;
+    let ballcam_id = get_object_id(replay, toggle_object);
     //let camera_create   = replay.objects.iter().position(|pp| pp == "TAGame.Default__CameraSettingsActor_TA").unwrap() as i32;
 
     //let mut min_time: Option<f32> = None;
@@ -962,7 +1268,7 @@ fn get_ballcam_list(ltl: &LifetimeList, replay: &Replay, pid: &UniqueId, idx_lis
 //                        actor_exists = true;
 //                    }
 //                }
-                ChangeEvent::U(ua) if ua.object_id.0 == ballcam_id => {
+                ChangeEvent::U(ua) if Some(ua.object_id.0) == ballcam_id => {
                     if let Attribute::Boolean(u_state) = &ua.attribute {
                         ret.push(BallcamEvent::from(ev.frame, ev.time, BallcamVariant::Update(*u_state)));
                     }
@@ -985,15 +1291,40 @@ fn get_ballcam_list(ltl: &LifetimeList, replay: &Replay, pid: &UniqueId, idx_lis
     return ret;
 }
 
-fn new_ballcam_lifetimes(ltl: &LifetimeList, replay: &Replay) -> HashMap<UniqueId, PlayerResult> {
-    let mut results: HashMap<UniqueId, PlayerResult> = HashMap::new();
+/// The ballcam results, plus the camera settings, swivel/rear view and ping stats that
+/// `parse_replay_file` has always returned in `PlayerResult`.
+pub struct BallcamMetric;
 
-    let player_buckets = player_id_buckets(ltl, replay);
-    let disconnect_players = get_disconnect_players(ltl, replay);
-    let game_state_changes: Vec<GameStateEvent> = get_state_changes(ltl, replay);
-    for (pid, idx_list) in player_buckets.iter() {
-        let ballcam_events = get_ballcam_list(ltl, replay, pid, idx_list, disconnect_players.get(pid));
-        if let Some(res) = process_ballcam(ltl, replay, pid, &ballcam_events, &game_state_changes) {
+impl ReplayMetric for BallcamMetric {
+    type Output = PlayerResult;
+
+    fn compute(&self, ctx: &MetricContext) -> HashMap<PlayerKey, PlayerResult> {
+        new_ballcam_lifetimes(ctx)
+    }
+}
+
+fn new_ballcam_lifetimes(ctx: &MetricContext) -> HashMap<PlayerKey, PlayerResult> {
+    let mut results: HashMap<PlayerKey, PlayerResult> = HashMap::new();
+
+    let (ltl, replay) = (ctx.lifetimes, ctx.replay);
+    let disconnect_players = &ctx.disconnects;
+    let game_state_changes = &ctx.game_states;
+    for (pid, idx_list) in ctx.players.iter() {
+        let ballcam_events = get_ballcam_list(ltl, replay, pid, idx_list, disconnect_players.get(pid), BALLCAM_OBJECT);
+        if let Some(mut res) = process_ballcam(ltl, replay, pid, &ballcam_events, game_state_changes) {
+            res.camera_settings = camera_settings::get_camera_settings(ltl, replay, idx_list, disconnect_players.get(pid));
+
+            let swivel_events = get_ballcam_list(ltl, replay, pid, idx_list, disconnect_players.get(pid), SWIVEL_OBJECT);
+            if let Some(swivel_res) = process_ballcam(ltl, replay, pid, &swivel_events, game_state_changes) {
+                res.swivel = ToggleResult::from(&swivel_res);
+            }
+            let behind_events = get_ballcam_list(ltl, replay, pid, idx_list, disconnect_players.get(pid), BEHIND_VIEW_OBJECT);
+            if let Some(behind_res) = process_ballcam(ltl, replay, pid, &behind_events, game_state_changes) {
+                res.behind_view = ToggleResult::from(&behind_res);
+            }
+
+            let ping_timeline = ping::get_ping_timeline(ltl, replay, idx_list, disconnect_players.get(pid));
+            res.ping = PingStats::from_timeline(&ping_timeline);
             results.insert(pid.clone(), res);
         }
     }
@@ -1012,7 +1343,65 @@ fn new_ballcam_lifetimes(ltl: &LifetimeList, replay: &Replay) -> HashMap<UniqueI
     results
 }
 
-fn process_ballcam(ltl: &LifetimeList, replay: &Replay, pid: &UniqueId, ball_events: &Vec<BallcamEvent>, game_events: &Vec<GameStateEvent>) -> Option<PlayerResult> {
+/// When a player's camera toggle changed, as `(time, new state)`, from the events of `get_ballcam_list`.
+pub(crate) struct ToggleTimeline {
+    /// The first entry is when the player's camera showed up, with ballcam off like `process_ballcam`
+    /// assumes. The rest are actual toggles
+    pub(crate) changes: Vec<(f32, bool)>,
+    /// When the player left, or the last event of their camera
+    pub(crate) end: f32,
+}
+
+impl ToggleTimeline {
+    pub(crate) fn ballcam(ltl: &LifetimeList, replay: &Replay, pid: &PlayerKey, idx_list: &Vec<usize>, disconnect_time: Option<&f32>) -> Self {
+        Self::from_events(&get_ballcam_list(ltl, replay, pid, idx_list, disconnect_time, BALLCAM_OBJECT))
+    }
+
+    fn from_events(events: &[BallcamEvent]) -> Self {
+        let mut changes: Vec<(f32, bool)> = vec![(events[0].info.time, false)];
+        for ev in events.iter() {
+            if let BallcamVariant::Update(state) = ev.variant {
+                if state != changes[changes.len() - 1].1 {
+                    changes.push((ev.info.time, state));
+                }
+            }
+        }
+        Self {
+            changes,
+            end: events[events.len() - 1].info.time,
+        }
+    }
+
+    /// The toggle's state at `time`, and when it was last toggled before that (`None` if it never was).
+    /// `None` if the player wasn't in the game then.
+    pub(crate) fn state_at(&self, time: f32) -> Option<(bool, Option<f32>)> {
+        if time < self.changes[0].0 || time > self.end {
+            return None;
+        }
+        let index = self.changes.partition_point(|&(change_time, _)| change_time <= time) - 1;
+        let last_toggle = if index == 0 { None } else { Some(self.changes[index].0) };
+        Some((self.changes[index].1, last_toggle))
+    }
+
+    /// The share of `start..end` the toggle was on, counting only the part the player was in the game for.
+    pub(crate) fn on_fraction(&self, start: f32, end: f32) -> Option<f32> {
+        let start = start.max(self.changes[0].0);
+        let end = end.min(self.end);
+        if end <= start {
+            return None;
+        }
+        let mut on_time = 0f32;
+        for (ind, &(change_time, state)) in self.changes.iter().enumerate() {
+            let next_time = self.changes.get(ind + 1).map_or(self.end, |&(time, _)| time);
+            if state {
+                on_time += (next_time.min(end) - change_time.max(start)).max(0f32);
+            }
+        }
+        Some(on_time / (end - start))
+    }
+}
+
+fn process_ballcam(ltl: &LifetimeList, replay: &Replay, pid: &PlayerKey, ball_events: &Vec<BallcamEvent>, game_events: &Vec<GameStateEvent>) -> Option<PlayerResult> {
     //eprintln!("\n\n\nProcessing Ballcam!!! for {:?}", pid);
     let mut ret = PlayerResult::new();
     let mut current_ballcam = false;
@@ -1081,13 +1470,13 @@ fn ballcam_lifetimes(ltl: &LifetimeList, replay: &Replay) -> BallcamResults {
     //new_ballcam_lifetimes(ltl, replay);
    
     //let mut results: HashMap<String, (f32, i32)> = HashMap::new();
-    let mut results: HashMap<UniqueId, (f32, i32)> = HashMap::new();
+    let mut results: HashMap<PlayerKey, (f32, i32)> = HashMap::new();
 
     //let mut self_percent = 0f32;
     //let mut other_total = 0f32;
     //let mut other_count = 0;
     for (pid, idx_list) in player_buckets.iter() {
-        get_ballcam_list(ltl, replay, pid, idx_list, disconnect_players.get(pid));
+        get_ballcam_list(ltl, replay, pid, idx_list, disconnect_players.get(pid), BALLCAM_OBJECT);
         //TODO eprintln!("\n\nCHECKING: {:?}", pid);
         let mut min_time: Option<f32> = None;
         let mut max_time: Option<f32> = None;
@@ -1179,7 +1568,7 @@ fn ballcam_lifetimes(ltl: &LifetimeList, replay: &Replay) -> BallcamResults {
         //dbg!(&swap_times);
 
         //results.insert(pid.to_owned().unwrap_or(format!("no-pid-{:?}", time::Instant::now())), (ballcam / total * 100f32, swaps));
-        results.insert(pid.to_owned(), (ballcam / total * 100f32, swaps));
+        results.insert(pid.clone(), (ballcam / total * 100f32, swaps));
         /*
         if pid.is_some() && pid.as_ref().unwrap() == target_player {
             self_percent = ballcam / total * 100f32;
@@ -1194,10 +1583,77 @@ fn ballcam_lifetimes(ltl: &LifetimeList, replay: &Replay) -> BallcamResults {
 }
 
 
-pub fn parse_replay_file(replay_file: &str) -> Result<(Metadata, HashMap<UniqueId, PlayerResult>), ()> {
-    let replay = parse_file(&replay_file).map_err(|e| {
+fn ballcam_registry() -> MetricRegistry {
+    let mut registry = MetricRegistry::new();
+    registry.register(BallcamMetric);
+    registry
+}
+
+fn read_replay_data<P: AsRef<Path>>(replay_file: P) -> Result<Vec<u8>, ()> {
+    archive::read_replay(replay_file).map_err(|e| {
+        eprintln!("\nHIDDEN ERROR:\n{}\n\n", e);
+    })
+}
+
+pub fn parse_replay_file<P: AsRef<Path>>(replay_file: P) -> Result<(Metadata, PlayerResults), ()> {
+    let data = read_replay_data(replay_file)?;
+    parse_replay_bytes(&data)
+}
+
+/// Same as `parse_replay_file`, for a replay that's already in memory.
+pub fn parse_replay_bytes(data: &[u8]) -> Result<(Metadata, PlayerResults), ()> {
+    let (metadata, mut results) = parse_replay_bytes_with(data, &ballcam_registry())?;
+    Ok((metadata, results.take::<BallcamMetric>().unwrap_or_default()))
+}
+
+/// Same as `parse_replay_file`, reading the whole replay from `reader` first (e.g. stdin).
+pub fn parse_replay_reader<R: Read>(mut reader: R) -> Result<(Metadata, PlayerResults), ()> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).map_err(|e| {
+        eprintln!("\nHIDDEN ERROR:\n{}\n\n", e);
+    })?;
+    parse_replay_bytes(&data)
+}
+
+/// Parse a replay once and run every metric in the registry on it.
+pub fn parse_replay_file_with<P: AsRef<Path>>(replay_file: P, registry: &MetricRegistry) -> Result<(Metadata, MetricResults), ()> {
+    let data = read_replay_data(replay_file)?;
+    parse_replay_bytes_with(&data, registry)
+}
+
+/// Like `parse_replay_file`, but checks the header against the filter first and only decodes
+/// the network frames (the slow part) if it matches. `Ok(None)` if it was filtered out.
+pub fn parse_replay_file_filtered<P: AsRef<Path>>(replay_file: P, filter: &ReplayFilter) -> Result<Option<(Metadata, PlayerResults)>, ()> {
+    Ok(parse_replay_file_with_filtered(replay_file, &ballcam_registry(), filter)?
+        .map(|(metadata, mut results)| (metadata, results.take::<BallcamMetric>().unwrap_or_default())))
+}
+
+/// `parse_replay_file_filtered` for the metrics in a registry.
+pub fn parse_replay_file_with_filtered<P: AsRef<Path>>(replay_file: P, registry: &MetricRegistry, filter: &ReplayFilter) -> Result<Option<(Metadata, MetricResults)>, ()> {
+    let data = read_replay_data(replay_file)?;
+    if !filter.matches(&parse_replay_data_metadata(&data)?) {
+        return Ok(None);
+    }
+    parse_replay_bytes_with(&data, registry).map(Some)
+}
+
+/// Get a replay's `Metadata` from its header, without decoding the network frames.
+pub fn parse_replay_metadata<P: AsRef<Path>>(replay_file: P) -> Result<Metadata, ()> {
+    let data = read_replay_data(replay_file)?;
+    parse_replay_data_metadata(&data)
+}
+
+fn parse_replay_data_metadata(data: &[u8]) -> Result<Metadata, ()> {
+    let header = parse_rl_header(data).map_err(|e| {
+        eprintln!("\nHIDDEN ERROR:\n{}\n\n", e);
+    })?;
+    get_metadata(&header)
+}
+
+/// Same as `parse_replay_file_with`, for a replay that's already in memory.
+pub fn parse_replay_bytes_with(data: &[u8], registry: &MetricRegistry) -> Result<(Metadata, MetricResults), ()> {
+    let replay = parse_rl(data).map_err(|e| {
         eprintln!("\nHIDDEN ERROR:\n{}\n\n", e);
-        ()
     })?;
     //let replay = parse_file(&replay_file).unwrap();
     let lifetimes = parse_lifetimes(&replay);
@@ -1211,58 +1667,14 @@ pub fn parse_replay_file(replay_file: &str) -> Result<(Metadata, HashMap<UniqueI
         println!("{} -> {:?} count", object, l.events.len());
     }
     */
-    let metadata = get_metadata(&replay);
-    let bresults = new_ballcam_lifetimes(&lifetimes, &replay);
+    let metadata = get_metadata(&replay)?;
+    let bresults = registry.run(&MetricContext::new(&replay, &lifetimes));
     //ballcam_lifetimes(&lifetimes, &replay);
     //eprintln!("\nDOING: {}, {:?}", replay_file, metadata);
     //eprintln!("RESULTS:\n {:?}\n", bresults);
     Ok((metadata, bresults))
 }
 
-pub fn reservation_stats(replay_file: &str, results: &mut HashMap<(Option<(bool, bool)>, (bool, bool)), i64>) -> Result<(), ()> {
-    let replay = parse_file(&replay_file).map_err(|e| {
-        eprintln!("\nHIDDEN ERROR:\n{}\n\n", e);
-        ()
-    })?;
-
-    let reservations = get_object_id(&replay, "ProjectX.GRI_X:Reservations").ok_or(())?;
-
-    let mut res_changes: HashMap<UniqueId, (bool, bool)> = HashMap::new();
-
-    replay.network_frames.as_ref().unwrap()
-        .frames.iter().enumerate()
-        .for_each(|(frame_id, fr)| {
-            fr.updated_actors.iter().for_each(|ua| {
-                if ua.object_id.0 == reservations {
-                    if let Attribute::Reservation(trev) = &ua.attribute {
-                        //eprintln!("\n\ntime: {}", fr.time);
-                        //dbg!(trev);
-                        if !res_changes.contains_key(&trev.unique_id) {
-                            *results.entry((None, (trev.unknown1, trev.unknown2))).or_insert(0) += 1;
-                        }
-                        let res_entry = res_changes.entry(trev.unique_id.clone()).or_insert((trev.unknown1, trev.unknown2));
-                        if (res_entry.0 != trev.unknown1 || res_entry.1 != trev.unknown2)
-                            // && !res_entry.0 && !res_entry.1 // only when was false previously
-                            {
-                                /*
-                            eprintln!("\n\ntime: {}", fr.time);
-                            eprintln!("was:");
-                            eprintln!("unknown1: {}", res_entry.0);
-                            eprintln!("unknown2: {}", res_entry.1);
-                            dbg!(trev);
-                            */
-                            //panic!("rejoined?");
-                            *results.entry(( Some(*res_entry), (trev.unknown1, trev.unknown2) )).or_insert(0) += 1;
-                        }
-                        res_entry.0 = trev.unknown1;
-                        res_entry.1 = trev.unknown2;
-                    }
-                }
-            });
-        });
-
-    Ok(())
-}
 /*
 pub fn parse_replay_file(replay_file: &str) -> Result<(Metadata, BallcamResults), ()> {
     let replay = parse_file(&replay_file).map_err(|e| {
@@ -1271,10 +1683,128 @@ pub fn parse_replay_file(replay_file: &str) -> Result<(Metadata, BallcamResults)
     })?;
     //let replay = parse_file(&replay_file).unwrap();
     let lifetimes = parse_lifetimes(&replay);
-    let metadata = get_metadata(&replay);
+    let metadata = get_metadata(&replay)?;
     let bresults = ballcam_lifetimes(&lifetimes, &replay);
     //eprintln!("\nDOING: {}, {:?}", replay_file, metadata);
     //eprintln!("RESULTS:\n {:?}\n", bresults);
     Ok((metadata, bresults))
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // joins at 10, ballcam on at 20, off at 30, leaves at 50
+    fn timeline() -> ToggleTimeline {
+        ToggleTimeline {
+            changes: vec![(10.0, false), (20.0, true), (30.0, false)],
+            end: 50.0,
+        }
+    }
+
+    #[test]
+    fn state_outside_the_game() {
+        assert_eq!(timeline().state_at(9.9), None);
+        assert_eq!(timeline().state_at(50.1), None);
+    }
+
+    #[test]
+    fn state_at_the_edges() {
+        assert_eq!(timeline().state_at(10.0), Some((false, None)));
+        assert_eq!(timeline().state_at(50.0), Some((false, Some(30.0))));
+    }
+
+    #[test]
+    fn state_at_and_between_toggles() {
+        assert_eq!(timeline().state_at(15.0), Some((false, None)));
+        assert_eq!(timeline().state_at(20.0), Some((true, Some(20.0))));
+        assert_eq!(timeline().state_at(25.0), Some((true, Some(20.0))));
+        assert_eq!(timeline().state_at(30.0), Some((false, Some(30.0))));
+    }
+
+    #[test]
+    fn state_without_toggles() {
+        let never = ToggleTimeline { changes: vec![(0.0, false)], end: 0.0 };
+        assert_eq!(never.state_at(0.0), Some((false, None)));
+        assert_eq!(never.state_at(1.0), None);
+    }
+
+    #[test]
+    fn on_fraction_outside_the_game() {
+        assert_eq!(timeline().on_fraction(0.0, 10.0), None);
+        assert_eq!(timeline().on_fraction(50.0, 60.0), None);
+        assert_eq!(timeline().on_fraction(25.0, 25.0), None);
+    }
+
+    #[test]
+    fn on_fraction_of_windows() {
+        assert_eq!(timeline().on_fraction(10.0, 50.0), Some(0.25));
+        assert_eq!(timeline().on_fraction(20.0, 30.0), Some(1.0));
+        assert_eq!(timeline().on_fraction(30.0, 40.0), Some(0.0));
+        assert_eq!(timeline().on_fraction(15.0, 25.0), Some(0.5));
+        // only the part from joining counts
+        assert_eq!(timeline().on_fraction(0.0, 30.0), Some(0.5));
+    }
+
+    #[test]
+    fn results_round_trip_through_json() {
+        let metadata = Metadata::new(Some(String::from("friday")), NaiveDate::from_ymd_opt(2023, 5, 6).unwrap(), String::from("Ranked Doubles"));
+        let mut results = PlayerResults::new();
+        let mut res = PlayerResult::new();
+        res.swaps_all = 12;
+        res.ballcam_all = 200.5;
+        res.total_time_all = 300.0;
+        results.insert("steam-76561198000000000-0".parse().unwrap(), res);
+        results.insert("epic--0".parse().unwrap(), PlayerResult::new());
+
+        let json = serde_json::to_string(&(metadata, results)).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        // player keys are written as their ids
+        assert!(value[1].get("steam-76561198000000000-0").is_some());
+        assert!(value[1].get("epic--0").is_some());
+
+        let (metadata, results): (Metadata, PlayerResults) = serde_json::from_str(&json).unwrap();
+        assert_eq!(metadata.date, NaiveDate::from_ymd_opt(2023, 5, 6).unwrap());
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[&"steam-76561198000000000-0".parse().unwrap()].swaps_all, 12);
+        assert_eq!(serde_json::to_value(&(metadata, results)).unwrap(), value);
+    }
+
+    fn metadata(date: (i32, u32, u32), playlist: &str) -> Metadata {
+        Metadata::new(None, NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap(), playlist.to_string())
+    }
+
+    #[test]
+    fn empty_filter_keeps_everything() {
+        let filter = ReplayFilter::default();
+        assert!(filter.matches(&metadata((2015, 7, 7), "TAGame.Replay_Soccar_TA-2")));
+        assert!(filter.matches(&metadata((2030, 1, 1), "")));
+    }
+
+    #[test]
+    fn filter_date_bounds() {
+        let day = |d: u32| NaiveDate::from_ymd_opt(2023, 5, d);
+        let from = ReplayFilter { min_date: day(10), ..ReplayFilter::default() };
+        assert!(!from.matches(&metadata((2023, 5, 9), "x")));
+        assert!(from.matches(&metadata((2023, 5, 10), "x")));
+        assert!(from.matches(&metadata((2030, 1, 1), "x")));
+
+        let until = ReplayFilter { max_date: day(10), ..ReplayFilter::default() };
+        assert!(until.matches(&metadata((2015, 1, 1), "x")));
+        assert!(until.matches(&metadata((2023, 5, 10), "x")));
+        assert!(!until.matches(&metadata((2023, 5, 11), "x")));
+
+        let between = ReplayFilter { min_date: day(10), max_date: day(12), ..ReplayFilter::default() };
+        assert!(!between.matches(&metadata((2023, 5, 9), "x")));
+        assert!(between.matches(&metadata((2023, 5, 11), "x")));
+        assert!(!between.matches(&metadata((2023, 5, 13), "x")));
+    }
+
+    #[test]
+    fn filter_playlists() {
+        let filter = ReplayFilter { playlists: vec![String::from("TAGame.Replay_Soccar_TA-2")], ..ReplayFilter::default() };
+        assert!(filter.matches(&metadata((2023, 5, 6), "TAGame.Replay_Soccar_TA-2")));
+        assert!(!filter.matches(&metadata((2023, 5, 6), "TAGame.Replay_Soccar_TA-3")));
+    }
+}
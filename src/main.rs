@@ -1,17 +1,413 @@
-use std::{time, collections::HashMap, ops::Deref};
-use boxcars::UniqueId;
+use std::{time, collections::{HashMap, HashSet}, ops::Deref};
+use std::{env, io, process};
+use std::path::PathBuf;
+use std::str::FromStr;
 use chrono::NaiveDate;
 use::dotenv;
-use replay_stats_rl::{Metadata, PlayerResult};
+use replay_stats_rl::{BallcamMetric, Metadata, PlayerResult, PlayerResults, ReplayFilter};
+use replay_stats_rl::discovery::{self, ReplaySource};
+use replay_stats_rl::dump::{DumpFilter, DumpFormat};
+use replay_stats_rl::dwell::{DwellMetric, DwellStats, DwellTimes};
+use replay_stats_rl::goals::{GoalBallcam, GoalMetric, GoalSide, GoalSummary};
+use replay_stats_rl::metrics::{MetricRegistry, MetricResults};
+use replay_stats_rl::ping::PingMetric;
+use replay_stats_rl::index::ReplayInfo;
+use replay_stats_rl::person::Person;
+use replay_stats_rl::player_key::PlayerKey;
+use replay_stats_rl::roster::Roster;
+use replay_stats_rl::touches::{TouchBallcam, TouchMetric, TouchSummary, TouchWindowSummary};
+use replay_stats_rl::reservations::{ReservationEventKind, ReservationReport};
+use replay_stats_rl::watch::ReplayWatcher;
+
+const USAGE: &str = "Usage:
+    replay_stats_rl
+    replay_stats_rl reservations [REPLAY_FILE...]
+    replay_stats_rl dump REPLAY_FILE [--object NAME] [--actor ID] [--frames START..END] [--time START..END] [--player ID] [--json]
+    replay_stats_rl watch
+    replay_stats_rl roster [ROSTER_FILE]
+    replay_stats_rl compare PLAYER_ID PLAYER_ID...
+    replay_stats_rl dwell [REPLAY_FILE...]
+    replay_stats_rl goals [REPLAY_FILE...]
+    replay_stats_rl touches [REPLAY_FILE...]
+    replay_stats_rl index [--sort date|length|map|playlist|file] [--reverse] [--from DATE] [--to DATE] [--playlist PLAYLIST]... [--map NAME] [--player NAME] [--json]";
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|arg| arg.as_str()) {
+        None => plots(),
+        Some("reservations") => reservations(&args[1..]),
+        Some("dump") => dump(&args[1..]),
+        Some("index") => index(&args[1..]),
+        Some("watch") => watch(),
+        Some("roster") => roster(&args[1..]),
+        Some("compare") => compare(&args[1..]),
+        Some("dwell") => dwell(&args[1..]),
+        Some("goals") => goals(&args[1..]),
+        Some("touches") => touches(&args[1..]),
+        Some(other) => usage_error(&format!("Unknown command: {}", other)),
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(1);
+}
+
+fn config_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn parse_arg<T: FromStr>(flag: &str, value: &str) -> T {
+    value.parse().unwrap_or_else(|_| usage_error(&format!("Invalid value for {}: {}", flag, value)))
+}
+
+/// Parse `START..END`, where either side can be left out.
+fn parse_range<T: FromStr + Default>(flag: &str, value: &str, max: T) -> (T, T) {
+    let (start, end) = value.split_once("..").unwrap_or_else(|| usage_error(&format!("{} should look like START..END", flag)));
+    (
+        if start.is_empty() { T::default() } else { parse_arg(flag, start) },
+        if end.is_empty() { max } else { parse_arg(flag, end) },
+    )
+}
+
+/// Dump the events of a replay, for when the ballcam numbers look wrong. See `DumpFilter` for what the options match.
+fn dump(args: &[String]) {
+    let mut filter = DumpFilter::default();
+    let mut format = DumpFormat::Text;
+    let mut replay_file: Option<&String> = None;
+
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        let mut value = || args_iter.next().unwrap_or_else(|| usage_error(&format!("{} needs a value", arg))).as_str();
+        match arg.as_str() {
+            "--object" => filter.object = Some(value().to_string()),
+            "--actor" => filter.actor_id = Some(parse_arg(arg, value())),
+            "--frames" => filter.frames = Some(parse_range(arg, value(), usize::MAX)),
+            "--time" => filter.times = Some(parse_range(arg, value(), f32::MAX)),
+            "--player" => filter.player = Some(parse_arg(arg, value())),
+            "--json" => format = DumpFormat::JsonLines,
+            _ if replay_file.is_none() && !arg.starts_with("--") => replay_file = Some(arg),
+            _ => usage_error(&format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    let replay_file = replay_file.unwrap_or_else(|| usage_error("dump needs a REPLAY_FILE"));
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    if replay_stats_rl::dump::dump_replay(replay_file, &filter, format, &mut out).is_err() {
+        process::exit(1);
+    }
+}
+
+/// Print reservation flag transitions over all the replays, and the replays where the
+/// reservation based disconnect doesn't line up with the camera deletion.
+/// If files are given, print every player's join/leave timeline for them too.
+fn reservations(files: &[String]) {
+    let show_all_timelines = !files.is_empty();
+    let replays = if files.is_empty() {
+        replay_files()
+    } else {
+        files.iter().map(PathBuf::from).collect()
+    };
+
+    let mut totals = ReservationReport::default();
+    for rfile in replays.iter() {
+        let report = match replay_stats_rl::reservations::reservation_report(rfile) {
+            Ok(report) => report,
+            Err(()) => continue,
+        };
+        totals.add_transitions(&report);
+        if !show_all_timelines && report.disconnect_mismatches.is_empty() {
+            continue;
+        }
+
+        println!("\nFile: {}", rfile.display());
+        for mismatch in report.disconnect_mismatches.iter() {
+            println!("  MISMATCH {}: reservation disconnect at {:?}, camera deleted at {:?}",
+                mismatch.player, mismatch.reservation_time, mismatch.camera_deleted_time);
+        }
+        for (pid, timeline) in report.timelines.iter() {
+            if !show_all_timelines && !report.disconnect_mismatches.iter().any(|mm| &mm.player == pid) {
+                continue;
+            }
+            println!("  {}:", pid);
+            for ev in timeline.iter() {
+                let kind = match ev.kind {
+                    ReservationEventKind::Join => "join",
+                    ReservationEventKind::Leave => "leave",
+                    ReservationEventKind::Rejoin => "rejoin",
+                    ReservationEventKind::Changed => "changed",
+                };
+                println!("    {:>8.2}s (frame {:>5})  {:<8} {:?}", ev.time, ev.frame, kind, ev.flags);
+            }
+        }
+    }
+
+    println!("\nReservation flag transitions (unknown1, unknown2):");
+    let mut transitions: Vec<_> = totals.transitions.iter().collect();
+    transitions.sort_by_key(|(_, &count)| std::cmp::Reverse(count));
+    for (transition, count) in transitions {
+        println!("{:>8}  {:?} -> {:?}", count, transition.from, transition.to);
+    }
+}
+
+/// List the replays from their headers only, to find a specific match quickly.
+/// `--map` and `--player` match substrings, case insensitive. The date and playlist options start from the .env settings.
+fn index(args: &[String]) {
+    let mut filter = replay_filter();
+    let mut sort = String::from("date");
+    let mut reverse = false;
+    let mut map: Option<String> = None;
+    let mut player: Option<String> = None;
+    let mut json = false;
+
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        let mut value = || args_iter.next().unwrap_or_else(|| usage_error(&format!("{} needs a value", arg))).as_str();
+        match arg.as_str() {
+            "--sort" => sort = value().to_string(),
+            "--reverse" => reverse = true,
+            "--from" => filter.min_date = Some(parse_arg(arg, value())),
+            "--to" => filter.max_date = Some(parse_arg(arg, value())),
+            "--playlist" => filter.playlists.push(value().to_string()),
+            "--map" => map = Some(value().to_lowercase()),
+            "--player" => player = Some(value().to_lowercase()),
+            "--json" => json = true,
+            _ => usage_error(&format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    let mut infos: Vec<ReplayInfo> = replay_files().iter()
+        .filter_map(|rfile| replay_stats_rl::index::replay_info(rfile).ok())
+        .filter(|info| filter.matches(&info.metadata))
+        .filter(|info| map.as_ref().is_none_or(|m| info.map.as_ref().is_some_and(|im| im.to_lowercase().contains(m))))
+        .filter(|info| player.as_ref().is_none_or(|p| info.players.iter().any(|ip| ip.name.to_lowercase().contains(p))))
+        .collect();
+
+    match sort.as_str() {
+        "date" => infos.sort_by_key(|info| (info.metadata.date, info.timestamp)),
+        "length" => infos.sort_by(|a, b| a.length.unwrap_or(0f32).total_cmp(&b.length.unwrap_or(0f32))),
+        "map" => infos.sort_by(|a, b| a.map.cmp(&b.map)),
+        "playlist" => infos.sort_by(|a, b| a.metadata.playlist.cmp(&b.metadata.playlist)),
+        "file" => infos.sort_by(|a, b| a.file.cmp(&b.file)),
+        _ => usage_error(&format!("Can't sort by {}", sort)),
+    }
+    if reverse {
+        infos.reverse();
+    }
+
+    for info in infos.iter() {
+        if json {
+            match serde_json::to_string(info) {
+                Ok(line) => println!("{}", line),
+                Err(e) => eprintln!("Unable to write {} as JSON: {}", info.file.display(), e),
+            }
+            continue;
+        }
+        let length = info.length.map(|l| format!("{}:{:02}", l as i32 / 60, l as i32 % 60)).unwrap_or_default();
+        println!("{:<19}  {:<28}  {:<18}  {}v{}  {:>2}-{:<2}  {:>6}  {}  {}",
+            info.timestamp.map(|ts| ts.to_string()).unwrap_or_else(|| info.metadata.date.to_string()),
+            info.metadata.playlist,
+            info.map.as_deref().unwrap_or(""),
+            info.team_sizes.0, info.team_sizes.1,
+            info.score.0, info.score.1,
+            length,
+            info.players_by_team(),
+            info.file.display());
+    }
+}
+
+/// The optional `MAX_PING` setting, in ms.
+fn max_ping() -> Option<f32> {
+    dotenv::var("MAX_PING").ok().filter(|v| !v.is_empty()).map(|v| {
+        v.parse().unwrap_or_else(|_| config_error(&format!("MAX_PING should be a number, not {}", v)))
+    })
+}
+
+/// Build the replay filter from the optional `MIN_DATE`, `MAX_DATE` and `PLAYLISTS` settings.
+fn replay_filter() -> ReplayFilter {
+    let parse_date = |var: &str| dotenv::var(var).ok().filter(|v| !v.is_empty()).map(|v| {
+        NaiveDate::parse_from_str(&v, "%Y-%m-%d").unwrap_or_else(|_| config_error(&format!("{} should look like 2023-01-31, not {}", var, v)))
+    });
+    ReplayFilter {
+        min_date: parse_date("MIN_DATE"),
+        max_date: parse_date("MAX_DATE"),
+        playlists: dotenv::var("PLAYLISTS").ok()
+            .map(|v| v.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Where to find replays, from the REPLAY_DIR, REPLAY_RECURSIVE, REPLAY_INCLUDE, REPLAY_EXCLUDE
+/// and REPLAY_LIST_FILE settings.
+fn replay_source() -> ReplaySource {
+    let patterns = |var: &str| -> Vec<String> {
+        dotenv::var(var).ok()
+            .map(|v| v.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect())
+            .unwrap_or_default()
+    };
+
+    let mut source = ReplaySource::new();
+    if let Ok(dirs) = dotenv::var("REPLAY_DIR") {
+        env::split_paths(&dirs).filter(|dir| !dir.as_os_str().is_empty()).for_each(|dir| { source.root(dir); });
+    }
+    if let Ok(list_file) = dotenv::var("REPLAY_LIST_FILE") {
+        if !list_file.is_empty() {
+            source.list_file(list_file);
+        }
+    }
+    if source.roots.is_empty() && source.list_file.is_none() {
+        config_error("Please specify a REPLAY_DIR or REPLAY_LIST_FILE in the .env file");
+    }
+    source.recursive(dotenv::var("REPLAY_RECURSIVE").ok() == Some(String::from("true")));
+    for pattern in patterns("REPLAY_INCLUDE") {
+        source.include(&pattern).unwrap_or_else(|e| config_error(&format!("Bad REPLAY_INCLUDE pattern {}: {}", pattern, e)));
+    }
+    for pattern in patterns("REPLAY_EXCLUDE") {
+        source.exclude(&pattern).unwrap_or_else(|e| config_error(&format!("Bad REPLAY_EXCLUDE pattern {}: {}", pattern, e)));
+    }
+    source
+}
+
+fn replay_files() -> Vec<PathBuf> {
+    replay_source().discover().unwrap_or_else(|e| config_error(&format!("Unable to list replays: {}", e)))
+}
+
+/// Warn about the PlayStation ids in a setting that are written with a PSN name and that no replay
+/// has. They're probably from before PlayStation players were keyed by their online id, and
+/// would otherwise just match nothing.
+fn warn_unseen_psn_names<'a>(setting: &str, keys: impl IntoIterator<Item = &'a PlayerKey>, results: impl IntoIterator<Item = &'a PlayerResults>) {
+    let seen: HashSet<&PlayerKey> = results.into_iter().flat_map(|hm| hm.keys()).collect();
+    for key in keys.into_iter().filter(|key| key.is_psn_name() && !seen.contains(key)) {
+        eprintln!("Warning: {} has {}, which uses a PSN name. PlayStation players are keyed by their online id now, \
+            `ps4-<online id>-0`, and only replays without an online id still use the name, so it might not match anything. \
+            The player column of EXPORT_FILE has everyone's id.", setting, key);
+    }
+}
+
+/// The settings `plot_updated` gets called with.
+struct PlotSettings {
+    target: Person,
+    plot_other_players: bool,
+    plot_swap_rate: bool,
+    max_ping: Option<f32>,
+}
+
+impl PlotSettings {
+    fn from_env() -> Self {
+        Self {
+            target: target_person(),
+            plot_other_players: dotenv::var("PLOT_OTHER_PLAYERS").ok() == Some(String::from("true")),
+            plot_swap_rate: dotenv::var("PLOT_SWAP_RATE").ok() == Some(String::from("true")),
+            max_ping: max_ping(),
+        }
+    }
+}
+
+/// The person to plot: TARGET_PLAYER is one or more comma separated player ids, for everyone
+/// that plays on several accounts. TARGET_NAME is what to call them, the first id by default.
+fn target_person() -> Person {
+    let target_player = dotenv::var("TARGET_PLAYER").expect("Please specify a TARGET_PLAYER in the .env file");
+    let accounts: Vec<PlayerKey> = target_player.split(',')
+        .map(|acc| acc.trim())
+        .filter(|acc| !acc.is_empty())
+        .map(|acc| acc.parse().unwrap_or_else(|e| config_error(&format!("Bad TARGET_PLAYER: {}", e))))
+        .collect();
+    if accounts.is_empty() {
+        config_error("Please specify a TARGET_PLAYER in the .env file");
+    }
+    let name = dotenv::var("TARGET_NAME").ok().filter(|name| !name.is_empty())
+        .unwrap_or_else(|| accounts[0].to_string());
+    Person::new(&name, accounts)
+}
+
+/// `target_person`, for the commands where TARGET_PLAYER is optional. `None` if it's unset or empty.
+fn optional_target() -> Option<Person> {
+    dotenv::var("TARGET_PLAYER").ok()
+        .is_some_and(|target| !target.trim().is_empty())
+        .then(target_person)
+}
+
+fn export_file() -> Option<String> {
+    dotenv::var("EXPORT_FILE").ok().filter(|f| !f.is_empty())
+}
+
+fn parse_replays(replays: &[PathBuf], filter: &ReplayFilter) -> Vec<(PathBuf, Metadata, PlayerResults)> {
+    replays.iter().filter_map(|rfile| {
+        replay_stats_rl::parse_replay_file_filtered(rfile, filter).ok().flatten()
+            .map(|(md, bc)| (rfile.clone(), md, bc))
+    }).collect()
+}
+
+fn write_export(export_file: &str, results: &[(PathBuf, Metadata, PlayerResults)]) {
+    let rows: Vec<_> = results.iter().map(|(rfile, md, bc)| (rfile.as_path(), md, bc)).collect();
+    if let Err(e) = replay_stats_rl::export::write_export(export_file, &rows) {
+        eprintln!("Unable to write {}: {}", export_file, e);
+    }
+}
+
+/// Keep running, and process each replay saved in REPLAY_DIR: add it to the EXPORT_FILE and redo the plots.
+/// Everything already there is processed once at the start.
+fn watch() {
+    let settings = PlotSettings::from_env();
+    let filter = replay_filter();
+    let export_file = export_file();
+    let source = replay_source();
+    if source.roots.is_empty() {
+        config_error("Please specify a REPLAY_DIR to watch in the .env file");
+    }
+
+    // watching first, so nothing saved while the existing replays are parsed gets missed
+    let mut watcher = ReplayWatcher::new(source).unwrap_or_else(|e| config_error(&format!("Unable to watch the replay directories: {}", e)));
+    let mut results = parse_replays(&replay_files(), &filter);
+    if let Some(export_file) = &export_file {
+        write_export(export_file, &results);
+    }
+    warn_unseen_psn_names("TARGET_PLAYER", &settings.target.accounts, results.iter().map(|(_, _, bc)| bc));
+    generate_plots(&results, &settings, &mut Vec::new());
+
+    eprintln!("Processed {} replays, watching for new ones", results.len());
+    while let Some(rfile) = watcher.next_replay() {
+        match replay_stats_rl::parse_replay_file_filtered(&rfile, &filter) {
+            Ok(Some((md, bc))) => {
+                // the watcher reports absolute paths, discovery the ones from REPLAY_DIR
+                let absolute = discovery::absolute(&rfile);
+                let count = results.len();
+                results.retain(|(done, _, _)| discovery::absolute(done) != absolute);
+                let replaced = results.len() != count;
+                results.push((rfile.clone(), md, bc));
+                if let Some(export_file) = &export_file {
+                    // a replay saved again, or found by both the first scan and the watcher, would
+                    // otherwise get its rows in there twice
+                    if replaced {
+                        write_export(export_file, &results);
+                    } else {
+                        let (_, md, bc) = &results[results.len() - 1];
+                        if let Err(e) = replay_stats_rl::export::append_export(export_file, &rfile, md, bc) {
+                            eprintln!("Unable to write {}: {}", export_file, e);
+                        }
+                    }
+                }
+                generate_plots(&results, &settings, &mut Vec::new());
+                eprintln!("Added {}", rfile.display());
+            },
+            Ok(None) => eprintln!("Skipped {}, it doesn't match the filters", rfile.display()),
+            Err(()) => {
+                if !watcher.retry(&rfile) {
+                    eprintln!("Giving up on {}, it doesn't parse", rfile.display());
+                }
+            },
+        }
+    }
+}
+
+fn plots() {
     let start_time = time::Instant::now();
     let mut times = vec![("Start", time::Instant::now())];
 
     let replay_file = &dotenv::var("TEST_FILE").ok().expect("Please specify a TEST_FILE in the .env file");
-    let replay_dir = &dotenv::var("REPLAY_DIR").ok().expect("Please specify a REPLAY_DIR in the .env file");
-    let target_player = &dotenv::var("TARGET_PLAYER").ok().expect("Please specify a TARGET_PLAYER in the .env file");
-    let plot_other_players = dotenv::var("PLOT_OTHER_PLAYERS").ok() == Some(String::from("true"));
+    let settings = PlotSettings::from_env();
+    let filter = replay_filter();
 
 
     ////////////////////////////////////////////////////////////
@@ -19,14 +415,14 @@ fn main() {
     // Delete or Comment out the next line to enable the check
     /*
     let mut uid_counts: HashMap<String, i32> = HashMap::new();
-    replay_stats_rl::get_replay_list(replay_dir)
+    replay_files()
         .iter()
         .filter_map(|rfile| {
             replay_stats_rl::parse_replay_file(&rfile).ok()
         })
         .for_each(|pret| {
             for uid in pret.1.keys() {
-                *uid_counts.entry(replay_stats_rl::uid_to_string(uid)).or_insert(0) += 1;
+                *uid_counts.entry(uid.to_string()).or_insert(0) += 1;
             }
         });
     let mut uid_results: Vec<(String, i32)> = uid_counts.into_iter().collect();
@@ -41,20 +437,8 @@ fn main() {
 
     // dbg!(replay_stats_rl::parse_replay_file(replay_file).unwrap());
    /* 
-    let mut reservation_results: HashMap<(Option<(bool, bool)>, (bool, bool)), i64> = HashMap::new();
-    replay_stats_rl::get_replay_list(replay_dir).iter().for_each(|rfile| {
-        eprintln!("File: {}", rfile);
-        replay_stats_rl::reservation_stats(rfile, &mut reservation_results);
-    });
-    for key in reservation_results.keys() {
-        eprintln!("{:?} -> {}", key, reservation_results.get(key).unwrap());
-    }
-    return;
-    */
-
-   /* 
-    replay_stats_rl::get_replay_list(replay_dir).iter().take(500).for_each(|rfile| {
-        eprintln!("File: {}", rfile);
+    replay_files().iter().take(500).for_each(|rfile| {
+        eprintln!("File: {}", rfile.display());
         replay_stats_rl::parse_replay_file(rfile).unwrap();
     });
     return;
@@ -64,18 +448,271 @@ fn main() {
     */
     times.push(("Configs Loaded", time::Instant::now()));
 
-    let replays = replay_stats_rl::get_replay_list(replay_dir);
+    let replays = replay_files();
     times.push(("Got Replay List", time::Instant::now()));
 
-    let mut ballcam_results: Vec<(Metadata, HashMap<UniqueId, replay_stats_rl::PlayerResult>)> = replays.iter().filter_map(|rfile| {
-            replay_stats_rl::parse_replay_file(&rfile).ok()
-    }).collect();
-
+    let results = parse_replays(&replays, &filter);
     times.push(("Replays Processed", time::Instant::now()));
 
+    if let Some(export_file) = export_file() {
+        write_export(&export_file, &results);
+        times.push(("Results Exported", time::Instant::now()));
+    }
+
+    warn_unseen_psn_names("TARGET_PLAYER", &settings.target.accounts, results.iter().map(|(_, _, bc)| bc));
+    generate_plots(&results, &settings, &mut times);
+
+    for i in 1..times.len() {
+        eprintln!("{}:  {:?}", times[i].0, times[i].1 - times[i-1].1);
+    }
+
+    let main_duration = start_time.elapsed();
+    eprintln!("Time elapsed is: {:?}", main_duration);
+
+    //get_usage_stats();
+}
+
+/// Every replay's results sorted by date, without the high ping replays if MAX_PING is set.
+fn plot_data() -> Vec<(Metadata, PlayerResults)> {
+    let max_ping = max_ping();
+    let mut data: Vec<(Metadata, PlayerResults)> = parse_replays(&replay_files(), &replay_filter()).into_iter()
+        .map(|(_, md, bc)| (md, bc))
+        .collect();
+    data.sort_by_key(|(md, _)| md.date);
+    if let Some(max_ping) = max_ping {
+        data = replay_stats_rl::ping::filter_high_ping(data, max_ping);
+    }
+    data
+}
+
+/// Parse the given replays (all of REPLAY_DIR if none are given) with the registry's metrics,
+/// skipping the ones MIN_DATE, MAX_DATE, PLAYLISTS and MAX_PING leave out, like the plots do.
+fn parse_metric_replays(files: &[String], mut registry: MetricRegistry) -> Vec<(PathBuf, Metadata, MetricResults)> {
+    let replays = if files.is_empty() {
+        replay_files()
+    } else {
+        files.iter().map(PathBuf::from).collect()
+    };
+    let filter = replay_filter();
+    let max_ping = max_ping();
+    if max_ping.is_some() {
+        registry.register(PingMetric);
+    }
+    replays.into_iter().filter_map(|rfile| {
+        let (md, mut results) = match replay_stats_rl::parse_replay_file_with_filtered(&rfile, &registry, &filter) {
+            Ok(Some(parsed)) => parsed,
+            Ok(None) => return None,
+            Err(()) => {
+                eprintln!("Skipping {}, it couldn't be parsed", rfile.display());
+                return None;
+            },
+        };
+        if let Some(max_ping) = max_ping {
+            if results.take::<PingMetric>().is_some_and(|pings| replay_stats_rl::ping::any_above(&pings, max_ping)) {
+                return None;
+            }
+        }
+        Some((rfile, md, results))
+    }).collect()
+}
+
+fn print_dwell_stats(label: &str, stats: Option<DwellStats>) {
+    match stats {
+        Some(st) => println!("    {:<4} {:>4} stretches  median {:>6.2}s  p10 {:>6.2}s  p25 {:>6.2}s  p75 {:>6.2}s  p90 {:>6.2}s  mean {:>6.2}s  max {:>7.2}s",
+            label, st.count, st.median, st.p10, st.p25, st.p75, st.p90, st.mean, st.max),
+        None => println!("    {:<4}    0 stretches", label),
+    }
+}
+
+/// How long each player's ballcam on/off stretches last in each replay (all of REPLAY_DIR, unless
+/// files are given), then the same for TARGET_PLAYER over every replay, with its histograms.
+fn dwell(files: &[String]) {
+    let mut registry = MetricRegistry::new();
+    registry.register(DwellMetric);
+    let target = optional_target();
+
+    let mut target_total = DwellTimes::default();
+    for (rfile, _, mut results) in parse_metric_replays(files, registry) {
+        let mut players: Vec<(PlayerKey, DwellTimes)> = results.take::<DwellMetric>().unwrap_or_default().into_iter().collect();
+        players.sort_by(|a, b| a.0.cmp(&b.0));
+
+        println!("\nFile: {}", rfile.display());
+        for (pid, times) in players.iter() {
+            println!("  {}:", pid);
+            print_dwell_stats("on", times.on_stats());
+            print_dwell_stats("off", times.off_stats());
+            if target.as_ref().is_some_and(|person| person.owns(pid)) {
+                target_total.extend(times);
+            }
+        }
+    }
+
+    if let Some(person) = target {
+        println!("\nAll replays, {}:", person.name);
+        print_dwell_stats("on", target_total.on_stats());
+        print_dwell_stats("off", target_total.off_stats());
+        replay_stats_rl::dwell::plot_dwell_histogram(&target_total, "dwell-times");
+    }
+}
+
+/// Every player's ballcam state when each goal was scored, in each replay (all of REPLAY_DIR, unless
+/// files are given), then how often TARGET_PLAYER had ballcam on for goals for and against.
+fn goals(files: &[String]) {
+    let mut registry = MetricRegistry::new();
+    registry.register(GoalMetric).register(BallcamMetric);
+    let target = optional_target();
+
+    let mut target_goals: Vec<GoalBallcam> = Vec::new();
+    let (mut target_ballcam, mut target_total) = (0f32, 0f32);
+    for (rfile, _, mut results) in parse_metric_replays(files, registry) {
+        let ballcam: HashMap<PlayerKey, PlayerResult> = results.take::<BallcamMetric>().unwrap_or_default();
+        let mut players: Vec<(PlayerKey, Vec<GoalBallcam>)> = results.take::<GoalMetric>().unwrap_or_default().into_iter().collect();
+        players.sort_by(|a, b| a.0.cmp(&b.0));
+
+        println!("\nFile: {}", rfile.display());
+        let mut goal_frames: Vec<usize> = players.iter().flat_map(|(_, at_goals)| at_goals.iter().map(|gb| gb.goal.frame)).collect();
+        goal_frames.sort_unstable();
+        goal_frames.dedup();
+        for frame in goal_frames {
+            let mut scored = false;
+            for (pid, at_goals) in players.iter() {
+                let Some(gb) = at_goals.iter().find(|gb| gb.goal.frame == frame) else {
+                    continue;
+                };
+                if !scored {
+                    let team = match gb.goal.team {
+                        Some(0) => "blue",
+                        Some(1) => "orange",
+                        _ => "unknown team",
+                    };
+                    println!("  {:>8.2}s  goal by {} ({})", gb.goal.time, gb.goal.scorer.as_deref().unwrap_or("?"), team);
+                    scored = true;
+                }
+                let side = match gb.side {
+                    GoalSide::For => "for",
+                    GoalSide::Against => "against",
+                    GoalSide::Unknown => "?",
+                };
+                let since = gb.since_toggle.map(|st| format!("last toggled {:.2}s before", st)).unwrap_or(String::from("never toggled"));
+                println!("    {:<48} {:<8} ballcam {:<4} {}", pid.to_string(), side, if gb.ballcam { "on" } else { "off" }, since);
+            }
+        }
+
+        if let Some(person) = target.as_ref() {
+            for (pid, at_goals) in players.into_iter().filter(|(pid, _)| person.owns(pid)) {
+                target_goals.extend(at_goals);
+                if let Some(res) = ballcam.get(&pid) {
+                    target_ballcam += res.ballcam_active_only;
+                    target_total += res.total_time_active_only;
+                }
+            }
+        }
+    }
+
+    if let Some(person) = target {
+        let summary = GoalSummary::from_goals(&target_goals);
+        let percent = |on: usize, total: usize| if total == 0 { 0f32 } else { 100f32 * on as f32 / total as f32 };
+        println!("\nAll replays, {}:", person.name);
+        println!("  ballcam on at {:>4} of {:>4} goals for     ({:.1}%)", summary.ballcam_for, summary.goals_for, percent(summary.ballcam_for, summary.goals_for));
+        println!("  ballcam on at {:>4} of {:>4} goals against ({:.1}%)", summary.ballcam_against, summary.goals_against, percent(summary.ballcam_against, summary.goals_against));
+        if let Some(mean) = summary.mean_since_toggle_against {
+            println!("  goals against came {:.2}s after a toggle on average", mean);
+        }
+        if target_total > 0f32 {
+            println!("  ballcam on {:.1}% of active time overall", 100f32 * target_ballcam / target_total);
+        }
+    }
+}
+
+fn print_touch_summary(summary: &TouchSummary) {
+    let percent = |share: Option<f32>| share.map(|sh| format!("{:>5.1}%", 100f32 * sh)).unwrap_or(String::from("     -"));
+    for (label, window) in [("own", &summary.own), ("teammate", &summary.teammate), ("opponent", &summary.opponent)] {
+        let TouchWindowSummary { touches, before, after } = window;
+        println!("    {:<8} {:>4} touches  ballcam before {}  after {}", label, touches, percent(*before), percent(*after));
+    }
+}
+
+/// How much each player had ballcam on in the seconds before and after their own, their teammates'
+/// and their opponents' touches, in each replay (all of REPLAY_DIR, unless files are given), then
+/// the same for TARGET_PLAYER over every replay.
+fn touches(files: &[String]) {
+    let mut registry = MetricRegistry::new();
+    registry.register(TouchMetric);
+    let target = optional_target();
+
+    let mut target_touches: Vec<TouchBallcam> = Vec::new();
+    for (rfile, _, mut results) in parse_metric_replays(files, registry) {
+        let mut players: Vec<(PlayerKey, Vec<TouchBallcam>)> = results.take::<TouchMetric>().unwrap_or_default().into_iter().collect();
+        players.sort_by(|a, b| a.0.cmp(&b.0));
+
+        println!("\nFile: {}", rfile.display());
+        for (pid, around_touches) in players {
+            println!("  {}:", pid);
+            print_touch_summary(&TouchSummary::from_touches(&around_touches));
+            if target.as_ref().is_some_and(|person| person.owns(&pid)) {
+                target_touches.extend(around_touches);
+            }
+        }
+    }
+
+    if let Some(person) = target {
+        println!("\nAll replays, {}:", person.name);
+        print_touch_summary(&TouchSummary::from_touches(&target_touches));
+    }
+}
+
+/// Plot the given players' ballcam % together, to `outputs/compare.png`.
+fn compare(args: &[String]) {
+    let keys: Vec<PlayerKey> = args.iter()
+        .map(|arg| arg.parse().unwrap_or_else(|e| usage_error(&format!("{}", e))))
+        .collect();
+    if keys.is_empty() {
+        usage_error("compare needs at least one player id");
+    }
+    let data = plot_data();
+    warn_unseen_psn_names("compare", &keys, data.iter().map(|(_, bc)| bc));
+    replay_stats_rl::plot_players(&data, "compare", &keys);
+}
+
+/// Plot each member of the roster (ROSTER_FILE, unless a file is given), plot them all together,
+/// and print a leaderboard of their active time ballcam %, swaps per minute and games.
+fn roster(args: &[String]) {
+    let roster_file = args.first().cloned()
+        .or_else(|| dotenv::var("ROSTER_FILE").ok().filter(|f| !f.is_empty()))
+        .unwrap_or_else(|| usage_error("roster needs a ROSTER_FILE"));
+    let roster = Roster::load(&roster_file).unwrap_or_else(|e| config_error(&format!("Unable to read {}: {}", roster_file, e)));
+    let plot_other_players = dotenv::var("PLOT_OTHER_PLAYERS").ok() == Some(String::from("true"));
+    let data = plot_data();
+    warn_unseen_psn_names(&roster_file, roster.members.iter().flat_map(|member| member.accounts.iter()), data.iter().map(|(_, bc)| bc));
+
+    for member in roster.members.iter() {
+        let file_name: String = member.name.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+        replay_stats_rl::plot_updated(data.clone(), &format!("roster-{}", file_name), member, plot_other_players);
+    }
+    replay_stats_rl::plot_overlay(&data, "roster-overlay", &roster.members);
+
+    println!("{:<20}  {:>6}  {:>9}  {:>9}", "Player", "Games", "Ballcam %", "Swaps/min");
+    for row in replay_stats_rl::roster::leaderboard(&roster, &data) {
+        println!("{:<20}  {:>6}  {:>9.1}  {:>9.2}", row.name, row.games, row.ballcam_percent, row.swaps_per_minute);
+    }
+}
+
+/// Make every plot from the parsed replays, in any order.
+fn generate_plots(results: &[(PathBuf, Metadata, PlayerResults)], settings: &PlotSettings, times: &mut Vec<(&str, time::Instant)>) {
+    let target = &settings.target;
+    let plot_other_players = settings.plot_other_players;
+    let mut ballcam_results: Vec<(Metadata, PlayerResults)> = results.iter()
+        .map(|(_, md, bc)| (md.clone(), bc.clone()))
+        .collect();
+
     ballcam_results.sort_by_key(|(md, _)| {md.date});
     times.push(("Replays Sorted", time::Instant::now()));
 
+    if let Some(max_ping) = settings.max_ping {
+        ballcam_results = replay_stats_rl::ping::filter_high_ping(ballcam_results, max_ping);
+        times.push(("High Ping Replays Filtered", time::Instant::now()));
+    }
+
 
     //TODO dbg!(&ballcam_results, ballcam_results.len());
 
@@ -84,63 +721,62 @@ fn main() {
 
     //TODO dbg!(&ballcam_results, ballcam_results.len());
 
-    let after2023: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)> = ballcam_results.iter()
+    let after2023: Vec<(Metadata, PlayerResults)> = ballcam_results.iter()
         .filter(|r| r.0.date >= NaiveDate::from_ymd_opt(2023, 01, 01).unwrap())
         .map(|(md, bc)| {(md.clone(), bc.clone())})
         .collect();
 
-    let ones: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)> = ballcam_results.iter()
+    let ones: Vec<(Metadata, PlayerResults)> = ballcam_results.iter()
         .filter(|r| r.0.playlist == "TAGame.Replay_Soccar_TA-1")
         .map(|(md, bc)| {(md.clone(), bc.clone())})
         .collect();
 
-    let twos: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)> = ballcam_results.iter()
+    let twos: Vec<(Metadata, PlayerResults)> = ballcam_results.iter()
         .filter(|r| r.0.playlist == "TAGame.Replay_Soccar_TA-2")
         .map(|(md, bc)| {(md.clone(), bc.clone())})
         .collect();
 
-    let threes: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)> = ballcam_results.iter()
+    let threes: Vec<(Metadata, PlayerResults)> = ballcam_results.iter()
         .filter(|r| r.0.playlist == "TAGame.Replay_Soccar_TA-3")
         .map(|(md, bc)| {(md.clone(), bc.clone())})
         .collect();
 
-    let ones2023: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)> = ballcam_results.iter()
+    let ones2023: Vec<(Metadata, PlayerResults)> = ballcam_results.iter()
         .filter(|r| r.0.date >= NaiveDate::from_ymd_opt(2023, 01, 01).unwrap())
         .filter(|r| r.0.playlist == "TAGame.Replay_Soccar_TA-1")
         .map(|(md, bc)| {(md.clone(), bc.clone())})
         .collect();
 
-    let twos2023: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)> = ballcam_results.iter()
+    let twos2023: Vec<(Metadata, PlayerResults)> = ballcam_results.iter()
         .filter(|r| r.0.date >= NaiveDate::from_ymd_opt(2023, 01, 01).unwrap())
         .filter(|r| r.0.playlist == "TAGame.Replay_Soccar_TA-2")
         .map(|(md, bc)| {(md.clone(), bc.clone())})
         .collect();
 
-    let threes2023: Vec<(Metadata, HashMap<UniqueId, PlayerResult>)> = ballcam_results.iter()
+    let threes2023: Vec<(Metadata, PlayerResults)> = ballcam_results.iter()
         .filter(|r| r.0.date >= NaiveDate::from_ymd_opt(2023, 01, 01).unwrap())
         .filter(|r| r.0.playlist == "TAGame.Replay_Soccar_TA-3")
         .map(|(md, bc)| {(md.clone(), bc.clone())})
         .collect();
     times.push(("Datasets Generated", time::Instant::now()));
 
-    replay_stats_rl::plot_updated(ballcam_results, &format!("both-sides-ballcam-full-{}", false), target_player, plot_other_players);
-    replay_stats_rl::plot_updated(after2023, &format!("both-sides-ballcam-2023-later-{}", false), target_player, plot_other_players);
-    replay_stats_rl::plot_updated(ones, &format!("both-sides-ballcam-full-1s-{}", false), target_player, plot_other_players);
-    replay_stats_rl::plot_updated(twos, &format!("both-sides-ballcam-full-2s-{}", false), target_player, plot_other_players);
-    replay_stats_rl::plot_updated(threes, &format!("both-sides-ballcam-full-3s-{}", false), target_player, plot_other_players);
-    replay_stats_rl::plot_updated(ones2023, &format!("both-sides-ballcam-2023-1s-{}", false), target_player, plot_other_players);
-    replay_stats_rl::plot_updated(twos2023, &format!("both-sides-ballcam-2023-2s-{}", false), target_player, plot_other_players);
-    replay_stats_rl::plot_updated(threes2023, &format!("both-sides-ballcam-2023-3s-{}", false), target_player, plot_other_players);
-
-    times.push(("Plots Generated", time::Instant::now()));
-
-
-    for i in 1..times.len() {
-        eprintln!("{}:  {:?}", times[i].0, times[i].1 - times[i-1].1);
+    let datasets = [
+        ("full", ballcam_results),
+        ("2023-later", after2023),
+        ("full-1s", ones),
+        ("full-2s", twos),
+        ("full-3s", threes),
+        ("2023-1s", ones2023),
+        ("2023-2s", twos2023),
+        ("2023-3s", threes2023),
+    ];
+    for (name, dataset) in datasets {
+        let file = format!("both-sides-ballcam-{}-{}", name, false);
+        if settings.plot_swap_rate {
+            replay_stats_rl::plot_swap_rate(&dataset, &file, target);
+        }
+        replay_stats_rl::plot_updated(dataset, &file, target, plot_other_players);
     }
 
-    let main_duration = start_time.elapsed();
-    eprintln!("Time elapsed is: {:?}", main_duration);
-
-    //get_usage_stats();
+    times.push(("Plots Generated", time::Instant::now()));
 }
@@ -0,0 +1,92 @@
+use boxcars::Replay;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use crate::resolver::PlayerResolver;
+use crate::player_key::PlayerKey;
+use crate::{get_disconnect_players, get_state_changes, player_id_buckets, GameStateEvent, LifetimeList};
+
+/// Everything a metric gets to look at for one replay. Built once per replay and shared by every metric.
+pub struct MetricContext<'a> {
+    pub replay: &'a Replay,
+    pub lifetimes: &'a LifetimeList<'a>,
+    /// `Countdown`/`Active`/`Goal` changes, in order
+    pub game_states: Vec<GameStateEvent>,
+    /// Each player's camera lifetimes, as indexes into `lifetimes.lifetimes()`
+    pub players: HashMap<PlayerKey, Vec<usize>>,
+    /// When each player that left early disconnected
+    pub disconnects: HashMap<PlayerKey, f32>,
+    pub resolver: PlayerResolver<'a>,
+}
+
+impl<'a> MetricContext<'a> {
+    pub fn new(replay: &'a Replay, lifetimes: &'a LifetimeList<'a>) -> Self {
+        Self {
+            replay,
+            lifetimes,
+            game_states: get_state_changes(lifetimes, replay),
+            players: player_id_buckets(lifetimes, replay),
+            disconnects: get_disconnect_players(lifetimes, replay),
+            resolver: PlayerResolver::new(lifetimes, replay),
+        }
+    }
+}
+
+/// A per-player stat computed from a parsed replay. Register it in a `MetricRegistry` to run it
+/// alongside the others in `parse_replay_file_with`.
+pub trait ReplayMetric {
+    type Output: 'static;
+
+    fn compute(&self, ctx: &MetricContext) -> HashMap<PlayerKey, Self::Output>;
+}
+
+// object safe version of ReplayMetric, so the registry can hold metrics with different outputs
+trait AnyMetric {
+    fn compute_any(&self, ctx: &MetricContext) -> Box<dyn Any>;
+}
+
+impl<M: ReplayMetric> AnyMetric for M {
+    fn compute_any(&self, ctx: &MetricContext) -> Box<dyn Any> {
+        Box::new(self.compute(ctx))
+    }
+}
+
+/// The metrics to run for each replay. Holds at most one metric of each type.
+#[derive(Default)]
+pub struct MetricRegistry {
+    metrics: Vec<(TypeId, Box<dyn AnyMetric>)>,
+}
+
+impl MetricRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a metric, replacing any metric of the same type.
+    pub fn register<M: ReplayMetric + 'static>(&mut self, metric: M) -> &mut Self {
+        self.metrics.retain(|(tid, _)| *tid != TypeId::of::<M>());
+        self.metrics.push((TypeId::of::<M>(), Box::new(metric)));
+        self
+    }
+
+    pub fn run(&self, ctx: &MetricContext) -> MetricResults {
+        MetricResults {
+            results: self.metrics.iter().map(|(tid, metric)| (*tid, metric.compute_any(ctx))).collect(),
+        }
+    }
+}
+
+/// The outputs of a `MetricRegistry` run, looked up by metric type.
+pub struct MetricResults {
+    results: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl MetricResults {
+    pub fn get<M: ReplayMetric + 'static>(&self) -> Option<&HashMap<PlayerKey, M::Output>> {
+        self.results.get(&TypeId::of::<M>()).and_then(|res| res.downcast_ref())
+    }
+
+    pub fn take<M: ReplayMetric + 'static>(&mut self) -> Option<HashMap<PlayerKey, M::Output>> {
+        self.results.remove(&TypeId::of::<M>()).and_then(|res| res.downcast().ok()).map(|res| *res)
+    }
+}
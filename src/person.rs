@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use crate::player_key::PlayerKey;
+use crate::{PlayerResult, PlayerResults};
+
+/// Someone we track, with every account they play on (e.g. Steam and Epic).
+/// Their accounts are counted together as them, and never as other players.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Person {
+    pub name: String,
+    pub accounts: Vec<PlayerKey>,
+}
+
+impl Person {
+    pub fn new(name: &str, accounts: Vec<PlayerKey>) -> Self {
+        Self {
+            name: name.to_string(),
+            accounts,
+        }
+    }
+
+    pub fn owns(&self, key: &PlayerKey) -> bool {
+        self.accounts.contains(key)
+    }
+
+    /// Whether any of the person's accounts played in the replay.
+    pub fn played_in(&self, results: &PlayerResults) -> bool {
+        self.accounts.iter().any(|acc| results.contains_key(acc))
+    }
+
+    /// The person's results in one replay, over all their accounts in it. Times and toggle counts
+    /// (ballcam, swivel and behind view) are added up. `ping` and `camera_settings` come from the
+    /// first of their accounts that played.
+    pub fn result_in(&self, results: &PlayerResults) -> Option<PlayerResult> {
+        let mut played = self.accounts.iter().filter_map(|acc| results.get(acc));
+        let mut ret = played.next()?.clone();
+        for res in played {
+            ret.swaps_all += res.swaps_all;
+            ret.swaps_with_freeze += res.swaps_with_freeze;
+            ret.swaps_active_only += res.swaps_active_only;
+            ret.ballcam_all += res.ballcam_all;
+            ret.ballcam_with_freeze += res.ballcam_with_freeze;
+            ret.ballcam_active_only += res.ballcam_active_only;
+            ret.total_time_all += res.total_time_all;
+            ret.total_time_with_freeze += res.total_time_with_freeze;
+            ret.total_time_active_only += res.total_time_active_only;
+            ret.swivel.add(&res.swivel);
+            ret.behind_view.add(&res.behind_view);
+        }
+        Some(ret)
+    }
+}
@@ -0,0 +1,162 @@
+use boxcars::Replay;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::metrics::{MetricContext, ReplayMetric};
+use crate::player_key::PlayerKey;
+use crate::{get_pings_from_cam, LifetimeList, Metadata, PlayerResults};
+
+/// Summary of a player's ping over a replay, in ms. `Engine.PlayerReplicationInfo:Ping` is replicated
+/// as ms / 4 to fit in a byte, `get_ping_timeline` scales it back.
+///
+/// The ping is only replicated when it changes, so `avg` and `p95` weigh each sample by how long it
+/// lasted rather than counting samples: a jittery second doesn't outweigh a steady minute.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PingStats {
+    pub min: u16,
+    pub avg: f32,
+    pub max: u16,
+    pub p95: u16,
+    pub samples: usize,
+}
+
+impl PingStats {
+    pub fn from_timeline(timeline: &PingTimeline) -> Option<Self> {
+        let pings = &timeline.pings;
+        if pings.is_empty() {
+            return None;
+        }
+        // each sample lasts until the next one, the last one until the end
+        let mut weighted: Vec<(u16, f64)> = pings.iter().enumerate()
+            .map(|(ind, &(time, ping))| {
+                let next_time = pings.get(ind + 1).map_or(timeline.end, |&(next, _)| next);
+                (ping, (next_time - time).max(0f32) as f64)
+            })
+            .collect();
+        // all at the same moment, nothing to weigh them by
+        if weighted.iter().all(|&(_, duration)| duration == 0f64) {
+            weighted.iter_mut().for_each(|(_, duration)| *duration = 1f64);
+        }
+        let total: f64 = weighted.iter().map(|&(_, duration)| duration).sum();
+        let avg = weighted.iter().map(|&(ping, duration)| ping as f64 * duration).sum::<f64>() / total;
+
+        weighted.sort_by_key(|&(ping, _)| ping);
+        let mut so_far = 0f64;
+        let p95 = weighted.iter()
+            .find(|&&(_, duration)| {
+                so_far += duration;
+                so_far >= total * 0.95
+            })
+            .map_or(weighted[weighted.len() - 1].0, |&(ping, _)| ping);
+        Some(Self {
+            min: weighted[0].0,
+            avg: avg as f32,
+            max: weighted[weighted.len() - 1].0,
+            p95,
+            samples: pings.len(),
+        })
+    }
+}
+
+/// A player's `(time, ping in ms)` samples, sorted by time, and when the last one stops counting
+/// (when they disconnected, or the end of the replay).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PingTimeline {
+    pub pings: Vec<(f32, u16)>,
+    pub end: f32,
+}
+
+/// Each player's full ping series. Use `PingStats::from_timeline` to summarize it.
+pub struct PingMetric;
+
+impl ReplayMetric for PingMetric {
+    type Output = PingTimeline;
+
+    fn compute(&self, ctx: &MetricContext) -> HashMap<PlayerKey, PingTimeline> {
+        ctx.players.iter()
+            .map(|(pid, idx_list)| (pid.clone(), get_ping_timeline(ctx.lifetimes, ctx.replay, idx_list, ctx.disconnects.get(pid))))
+            .collect()
+    }
+}
+
+/// Get the ping series for a player from the PRIs their camera lifetimes point to, cut off when
+/// the player disconnected.
+pub(crate) fn get_ping_timeline(ltl: &LifetimeList, replay: &Replay, idx_list: &[usize], disconnect_time: Option<&f32>) -> PingTimeline {
+    let mut pings: Vec<(f32, u16)> = idx_list.iter()
+        .flat_map(|&cfi| get_pings_from_cam(cfi, ltl, replay))
+        .map(|(time, ping)| (time, ping as u16 * 4))
+        .filter(|&(time, _)| disconnect_time.is_none_or(|&dt| time < dt))
+        .collect();
+    pings.sort_by(|a, b| a.0.total_cmp(&b.0));
+    // several cameras can point at the same PRI
+    pings.dedup();
+    let replay_end = replay.network_frames.as_ref()
+        .and_then(|nf| nf.frames.last())
+        .map_or(0f32, |fr| fr.time);
+    PingTimeline {
+        pings,
+        end: disconnect_time.copied().unwrap_or(replay_end),
+    }
+}
+
+/// Whether any player's average ping (from `PingMetric`) was above `max_ping` ms.
+pub fn any_above(timelines: &HashMap<PlayerKey, PingTimeline>, max_ping: f32) -> bool {
+    timelines.values()
+        .filter_map(PingStats::from_timeline)
+        .any(|ping| ping.avg > max_ping)
+}
+
+/// Drop the replays where any player had an average ping above `max_ping` ms.
+pub fn filter_high_ping(data: Vec<(Metadata, PlayerResults)>, max_ping: f32) -> Vec<(Metadata, PlayerResults)> {
+    data.into_iter()
+        .filter(|(_, hm)| {
+            hm.values().all(|res| res.ping.as_ref().is_none_or(|ping| ping.avg <= max_ping))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeline(pings: &[(f32, u16)], end: f32) -> PingTimeline {
+        PingTimeline { pings: pings.to_vec(), end }
+    }
+
+    #[test]
+    fn no_samples() {
+        assert_eq!(PingStats::from_timeline(&timeline(&[], 300.0)), None);
+    }
+
+    #[test]
+    fn one_sample() {
+        let stats = PingStats::from_timeline(&timeline(&[(10.0, 40)], 300.0)).unwrap();
+        assert_eq!(stats, PingStats { min: 40, avg: 40.0, max: 40, p95: 40, samples: 1 });
+        // even if it's at the very end
+        let stats = PingStats::from_timeline(&timeline(&[(300.0, 40)], 300.0)).unwrap();
+        assert_eq!(stats.avg, 40.0);
+        assert_eq!(stats.p95, 40);
+    }
+
+    #[test]
+    fn weighted_by_time() {
+        // a steady 40 for 90 seconds, then a 10 second spike jumping between 200 and 240
+        let mut pings = vec![(0.0, 40)];
+        pings.extend((0..10).map(|ind| (90.0 + ind as f32, if ind % 2 == 0 { 200 } else { 240 })));
+        let stats = PingStats::from_timeline(&timeline(&pings, 100.0)).unwrap();
+        assert_eq!(stats.samples, 11);
+        assert_eq!((stats.min, stats.max), (40, 240));
+        assert!((stats.avg - (40.0 * 90.0 + 200.0 * 5.0 + 240.0 * 5.0) / 100.0).abs() < 1e-3);
+        // the spike is 10% of the time, so the 95th percentile is in it: 90% at 40, 95% at 200
+        assert_eq!(stats.p95, 200);
+    }
+
+    #[test]
+    fn p95_rank() {
+        // 20 one second samples: 95% of the time is reached at the 19th lowest
+        let pings: Vec<(f32, u16)> = (0..20).map(|ind| (ind as f32, ind as u16 * 4)).collect();
+        let stats = PingStats::from_timeline(&timeline(&pings, 20.0)).unwrap();
+        assert_eq!(stats.p95, 72);
+        assert_eq!(stats.avg, 38.0);
+    }
+}
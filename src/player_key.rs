@@ -0,0 +1,256 @@
+use boxcars::{RemoteId, UniqueId};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The platform part of a `PlayerKey`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Platform {
+    Steam,
+    Epic,
+    Xbox,
+    PsyNet,
+    Switch,
+    PlayStation,
+    QQ,
+    SplitScreen,
+}
+
+impl Platform {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Platform::Steam => "steam",
+            Platform::Epic => "epic",
+            Platform::Xbox => "xbox",
+            Platform::PsyNet => "psynet",
+            Platform::Switch => "switch",
+            Platform::PlayStation => "ps4",
+            Platform::QQ => "qq",
+            Platform::SplitScreen => "splitscreen",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        [
+            Platform::Steam, Platform::Epic, Platform::Xbox, Platform::PsyNet,
+            Platform::Switch, Platform::PlayStation, Platform::QQ, Platform::SplitScreen,
+        ].into_iter().find(|platform| platform.prefix() == prefix)
+    }
+}
+
+/// A player's id, the same across replays. Written as `<platform>-<id>-<local id>`, where the
+/// local id tells split screen players on the same account apart (0 for the main player):
+///
+/// - Steam: `steam-<SteamID64>-0`, e.g. `steam-76561198000000000-0`
+/// - Epic: `epic-<account id>-0`, the 32 hex digit Epic account id
+/// - Xbox: `xbox-<online id>-0`, the online id as 16 hex digits, least significant byte first
+/// - PsyNet: `psynet-<online id>-0`, 16 hex digits, least significant byte first like Xbox
+/// - Switch: `switch-<online id>-0`, the online id in decimal
+/// - PlayStation: `ps4-<online id>-0`, the online id in decimal, so the key stays the same when the
+///   player changes their PSN name. Replays without the online id (it's 0) use the name instead:
+///   `ps4-<PSN name>-0`. PSN names start with a letter, so the two can't be mixed up, but can contain `-`
+/// - QQ: `qq-<id>-0`, and split screen guests: `splitscreen-<id>-<local id>`
+///
+/// Epic ids and PSN names can be empty in some replays, which gives keys like `epic--0`.
+///
+/// This is the format of the `TARGET_PLAYER` setting.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct PlayerKey {
+    platform: Platform,
+    id: String,
+    local_id: u8,
+}
+
+// online ids are shown with their bytes in little endian order
+fn hex_le(id: u64) -> String {
+    id.to_le_bytes().map(|b| format!("{:02x}", b)).join("")
+}
+
+impl PlayerKey {
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// The platform specific part of the key, as written in it.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn local_id(&self) -> u8 {
+        self.local_id
+    }
+
+    /// Whether this is a PlayStation key with the PSN name as its id. All PlayStation keys used to
+    /// look like that, now only the ones from replays without an online id do.
+    pub fn is_psn_name(&self) -> bool {
+        self.platform == Platform::PlayStation && !self.id.is_empty() && self.id.parse::<u64>().is_err()
+    }
+}
+
+impl From<&UniqueId> for PlayerKey {
+    fn from(uid: &UniqueId) -> Self {
+        let (platform, id) = match &uid.remote_id {
+            RemoteId::QQ(rid) => (Platform::QQ, rid.to_string()),
+            RemoteId::Xbox(rid) => (Platform::Xbox, hex_le(*rid)),
+            RemoteId::Epic(rid) => (Platform::Epic, rid.clone()),
+            RemoteId::Steam(rid) => (Platform::Steam, rid.to_string()),
+            RemoteId::PsyNet(psy_id) => (Platform::PsyNet, hex_le(psy_id.online_id)),
+            RemoteId::Switch(switch_id) => (Platform::Switch, switch_id.online_id.to_string()),
+            RemoteId::PlayStation(psn_id) if psn_id.online_id != 0 => (Platform::PlayStation, psn_id.online_id.to_string()),
+            RemoteId::PlayStation(psn_id) => (Platform::PlayStation, psn_id.name.clone()),
+            RemoteId::SplitScreen(split_id) => (Platform::SplitScreen, split_id.to_string()),
+        };
+        Self {
+            platform,
+            id,
+            local_id: uid.local_id,
+        }
+    }
+}
+
+impl fmt::Display for PlayerKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.platform.prefix(), self.id, self.local_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePlayerKeyError(String);
+
+impl fmt::Display for ParsePlayerKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} isn't a player id like steam-76561198000000000-0", self.0)
+    }
+}
+
+impl error::Error for ParsePlayerKeyError {}
+
+impl FromStr for PlayerKey {
+    type Err = ParsePlayerKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParsePlayerKeyError(s.to_string());
+        // the id itself can contain `-` (PSN names) or be empty, so split off the platform and local id from the ends
+        let (prefix, rest) = s.split_once('-').ok_or_else(err)?;
+        let (id, local_id) = rest.rsplit_once('-').ok_or_else(err)?;
+        let platform = Platform::from_prefix(prefix).ok_or_else(err)?;
+        let valid_id = match platform {
+            Platform::Steam | Platform::Switch | Platform::QQ | Platform::SplitScreen => id.parse::<u64>().is_ok(),
+            Platform::Xbox | Platform::PsyNet => id.len() == 16 && id.chars().all(|c| c.is_ascii_hexdigit()),
+            // anything `From<&UniqueId>` can give has to parse back, and these aren't checked there
+            Platform::Epic | Platform::PlayStation => true,
+        };
+        if !valid_id {
+            return Err(err());
+        }
+        Ok(Self {
+            platform,
+            id: match platform {
+                Platform::Xbox | Platform::PsyNet => id.to_lowercase(),
+                _ => id.to_string(),
+            },
+            local_id: local_id.parse().map_err(|_| err())?,
+        })
+    }
+}
+
+impl From<PlayerKey> for String {
+    fn from(key: PlayerKey) -> Self {
+        key.to_string()
+    }
+}
+
+impl TryFrom<String> for PlayerKey {
+    type Error = ParsePlayerKeyError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use boxcars::{Ps4Id, PsyNetId, SwitchId};
+
+    fn key(remote_id: RemoteId, local_id: u8) -> PlayerKey {
+        PlayerKey::from(&UniqueId { system_id: 0, remote_id, local_id })
+    }
+
+    fn round_trip(key: &PlayerKey) {
+        let parsed: PlayerKey = key.to_string().parse().unwrap();
+        assert_eq!(&parsed, key);
+        let json = serde_json::to_string(key).unwrap();
+        assert_eq!(&serde_json::from_str::<PlayerKey>(&json).unwrap(), key);
+    }
+
+    #[test]
+    fn every_platform_round_trips() {
+        let keys = [
+            (key(RemoteId::Steam(76561198000000000), 0), "steam-76561198000000000-0"),
+            (key(RemoteId::Epic(String::from("0123456789abcdef0123456789abcdef")), 0), "epic-0123456789abcdef0123456789abcdef-0"),
+            (key(RemoteId::Xbox(0x0011223344556677), 0), "xbox-7766554433221100-0"),
+            (key(RemoteId::PsyNet(PsyNetId { online_id: 0x0011223344556677, unknown1: vec![] }), 0), "psynet-7766554433221100-0"),
+            (key(RemoteId::Switch(SwitchId { online_id: 1234567890, unknown1: vec![] }), 0), "switch-1234567890-0"),
+            (key(RemoteId::PlayStation(Ps4Id { online_id: 987654321, name: String::from("some-name"), unknown1: vec![] }), 0), "ps4-987654321-0"),
+            (key(RemoteId::PlayStation(Ps4Id { online_id: 0, name: String::from("some-name-2"), unknown1: vec![] }), 0), "ps4-some-name-2-0"),
+            (key(RemoteId::QQ(12345), 0), "qq-12345-0"),
+            (key(RemoteId::SplitScreen(1), 1), "splitscreen-1-1"),
+        ];
+        for (key, written) in keys.iter() {
+            assert_eq!(&key.to_string(), written);
+            round_trip(key);
+        }
+    }
+
+    #[test]
+    fn psn_name_change_keeps_the_key() {
+        let before = key(RemoteId::PlayStation(Ps4Id { online_id: 42, name: String::from("old"), unknown1: vec![] }), 0);
+        let after = key(RemoteId::PlayStation(Ps4Id { online_id: 42, name: String::from("new"), unknown1: vec![] }), 0);
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn psn_name_with_dashes() {
+        let parsed: PlayerKey = "ps4-a-b--c-1".parse().unwrap();
+        assert_eq!(parsed.platform(), Platform::PlayStation);
+        assert_eq!(parsed.id(), "a-b--c");
+        assert_eq!(parsed.local_id(), 1);
+    }
+
+    #[test]
+    fn upper_case_hex_is_lowercased() {
+        let parsed: PlayerKey = "xbox-77665544332211AA-0".parse().unwrap();
+        assert_eq!(parsed.id(), "77665544332211aa");
+        assert_eq!(parsed, "xbox-77665544332211aa-0".parse().unwrap());
+        round_trip(&parsed);
+    }
+
+    #[test]
+    fn empty_ids_round_trip() {
+        let epic = key(RemoteId::Epic(String::new()), 0);
+        assert_eq!(epic.to_string(), "epic--0");
+        round_trip(&epic);
+        let psn = key(RemoteId::PlayStation(Ps4Id { online_id: 0, name: String::new(), unknown1: vec![] }), 0);
+        assert_eq!(psn.to_string(), "ps4--0");
+        round_trip(&psn);
+    }
+
+    #[test]
+    fn rejects_bad_keys() {
+        for bad in ["", "steam", "steam-123", "steam-abc-0", "steam--0", "xbox-123-0", "xbox-zz11223344556677-0", "nope-123-0", "steam-123-x", "steam-123-256"] {
+            assert!(bad.parse::<PlayerKey>().is_err(), "{} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn tells_psn_names_from_online_ids() {
+        let parse = |s: &str| s.parse::<PlayerKey>().unwrap();
+        assert!(parse("ps4-Some-Name-0").is_psn_name());
+        assert!(!parse("ps4-1234567890123456789-0").is_psn_name());
+        assert!(!parse("ps4--0").is_psn_name());
+        assert!(!parse("steam-76561198000000000-0").is_psn_name());
+    }
+}
@@ -0,0 +1,177 @@
+//! Diagnostics for the `ProjectX.GRI_X:Reservations` attribute.
+//!
+//! Each reservation carries two undocumented flags, `unknown1` and `unknown2`. From what I've seen
+//! both are true while a player is in the game, and at least one of them goes false once they leave.
+//! `get_disconnect_players` relies on that, so this is here to check how well it holds up.
+
+use boxcars::{Attribute, UniqueId};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::player_key::PlayerKey;
+use crate::{get_disconnect_players, get_object_id, parse_file, parse_lifetimes, player_id_buckets, ChangeEvent};
+
+/// How far apart (in seconds) the reservation disconnect and the camera deletion can be
+/// before we call it a disagreement.
+const DISCONNECT_TOLERANCE: f32 = 5.0;
+
+/// The `(unknown1, unknown2)` flags of a reservation.
+pub type ReservationFlags = (bool, bool);
+
+fn is_connected(flags: ReservationFlags) -> bool {
+    flags.0 && flags.1
+}
+
+/// A change in a player's reservation flags. `from` is `None` the first time we see the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ReservationTransition {
+    pub from: Option<ReservationFlags>,
+    pub to: ReservationFlags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReservationEventKind {
+    /// First time we see a reservation for the player
+    Join,
+    /// Flags went from both true to anything else
+    Leave,
+    /// Flags went back to both true after a `Leave`
+    Rejoin,
+    /// Any other flag change
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReservationEvent {
+    pub time: f32,
+    pub frame: usize,
+    pub kind: ReservationEventKind,
+    pub flags: ReservationFlags,
+}
+
+/// A player where `get_disconnect_players` and the camera actor deletion disagree.
+/// `None` means that side thinks the player stayed until the end.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisconnectMismatch {
+    pub player: PlayerKey,
+    pub reservation_time: Option<f32>,
+    pub camera_deleted_time: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReservationReport {
+    /// (De)serialized as a list of `[transition, count]`, JSON map keys have to be strings
+    #[serde(serialize_with = "transitions_to_list", deserialize_with = "transitions_from_list")]
+    pub transitions: HashMap<ReservationTransition, i64>,
+    pub timelines: HashMap<PlayerKey, Vec<ReservationEvent>>,
+    pub disconnect_mismatches: Vec<DisconnectMismatch>,
+}
+
+fn transitions_to_list<S: Serializer>(transitions: &HashMap<ReservationTransition, i64>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(transitions.iter())
+}
+
+fn transitions_from_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<ReservationTransition, i64>, D::Error> {
+    Ok(Vec::<(ReservationTransition, i64)>::deserialize(deserializer)?.into_iter().collect())
+}
+
+impl ReservationReport {
+    /// Add another report's transition counts to this one's.
+    pub fn add_transitions(&mut self, other: &ReservationReport) {
+        for (transition, count) in other.transitions.iter() {
+            *self.transitions.entry(*transition).or_insert(0) += count;
+        }
+    }
+}
+
+pub fn reservation_report<P: AsRef<Path>>(replay_file: P) -> Result<ReservationReport, ()> {
+    let replay = parse_file(replay_file).map_err(|e| {
+        eprintln!("\nHIDDEN ERROR:\n{}\n\n", e);
+    })?;
+
+    let reservations = get_object_id(&replay, "ProjectX.GRI_X:Reservations").ok_or(())?;
+
+    let mut report = ReservationReport::default();
+    let mut res_changes: HashMap<UniqueId, ReservationFlags> = HashMap::new();
+
+    let frames = &replay.network_frames.as_ref().ok_or(())?.frames;
+    frames.iter().enumerate().for_each(|(frame_id, fr)| {
+        fr.updated_actors.iter().for_each(|ua| {
+            if ua.object_id.0 != reservations {
+                return;
+            }
+            if let Attribute::Reservation(trev) = &ua.attribute {
+                let flags = (trev.unknown1, trev.unknown2);
+                let previous = res_changes.insert(trev.unique_id.clone(), flags);
+                if previous == Some(flags) {
+                    return;
+                }
+                *report.transitions.entry(ReservationTransition { from: previous, to: flags }).or_insert(0) += 1;
+
+                let timeline = report.timelines.entry(PlayerKey::from(&trev.unique_id)).or_default();
+                let has_left = timeline.iter().any(|ev| ev.kind == ReservationEventKind::Leave);
+                let kind = match previous {
+                    None => ReservationEventKind::Join,
+                    Some(prev) if is_connected(prev) && !is_connected(flags) => ReservationEventKind::Leave,
+                    Some(prev) if !is_connected(prev) && is_connected(flags) && has_left => ReservationEventKind::Rejoin,
+                    Some(_) => ReservationEventKind::Changed,
+                };
+                timeline.push(ReservationEvent { time: fr.time, frame: frame_id, kind, flags });
+            }
+        });
+    });
+
+    // compare the reservation based disconnects against when each player's last camera was deleted
+    let lifetimes = parse_lifetimes(&replay);
+    let disconnects = get_disconnect_players(&lifetimes, &replay);
+    let replay_end = frames.last().map(|fr| fr.time).unwrap_or(0f32);
+    for (pid, idx_list) in player_id_buckets(&lifetimes, &replay) {
+        let last_camera = idx_list.iter()
+            .map(|&ll| &lifetimes.list[ll])
+            .max_by_key(|lt| lt.events[0].frame)
+            .unwrap();
+        let camera_deleted_time = last_camera.events.last()
+            .filter(|ev| matches!(ev.event, ChangeEvent::D(_)))
+            .map(|ev| ev.time);
+        let reservation_time = disconnects.get(&pid).copied();
+
+        let agrees = match (reservation_time, camera_deleted_time) {
+            (None, None) => true,
+            // cameras are sometimes deleted as the replay ends
+            (None, Some(cam_time)) => cam_time >= replay_end - DISCONNECT_TOLERANCE,
+            (Some(res_time), Some(cam_time)) => (res_time - cam_time).abs() <= DISCONNECT_TOLERANCE,
+            _ => false,
+        };
+        if !agrees {
+            report.disconnect_mismatches.push(DisconnectMismatch {
+                player: pid,
+                reservation_time,
+                camera_deleted_time,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_round_trips_through_json() {
+        let mut report = ReservationReport::default();
+        report.transitions.insert(ReservationTransition { from: None, to: (true, true) }, 4);
+        report.transitions.insert(ReservationTransition { from: Some((true, true)), to: (true, false) }, 1);
+        let player: PlayerKey = "steam-76561198000000000-0".parse().unwrap();
+        report.timelines.insert(player.clone(), vec![ReservationEvent { time: 1.5, frame: 40, kind: ReservationEventKind::Join, flags: (true, true) }]);
+        report.disconnect_mismatches.push(DisconnectMismatch { player, reservation_time: Some(100.0), camera_deleted_time: None });
+
+        let json = serde_json::to_string(&report).unwrap();
+        let back: ReservationReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.transitions, report.transitions);
+        assert_eq!(back.timelines.len(), 1);
+        assert_eq!(back.disconnect_mismatches[0].reservation_time, Some(100.0));
+    }
+}
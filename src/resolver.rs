@@ -0,0 +1,96 @@
+use boxcars::{Attribute, Replay, UniqueId};
+
+use crate::{get_object_id, Lifetime, LifetimeList};
+
+/// Reference attributes that point from an actor towards its owner, in the order we try them.
+const OWNER_REFERENCES: [&str; 3] = [
+    "TAGame.CameraSettingsActor_TA:PRI",    // camera -> PRI
+    "Engine.Pawn:PlayerReplicationInfo",    // car -> PRI
+    "TAGame.CarComponent_TA:Vehicle",       // boost/jump/dodge/... component -> car
+];
+
+/// How many references we follow before giving up (component -> car -> PRI is the longest chain).
+const MAX_DEPTH: usize = 4;
+
+/// Figures out which player owns an actor, by following references like
+/// `TAGame.CameraSettingsActor_TA:PRI` until we reach a PlayerReplicationInfo and its `UniqueId`.
+pub struct PlayerResolver<'a> {
+    ltl: &'a LifetimeList<'a>,
+    unique_id: Option<i32>,
+    references: Vec<i32>,
+    team: Option<i32>,
+    /// `(object id, team number)` of the team archetypes, `Archetypes.Teams.Team0` and `Team1`
+    team_objects: Vec<(i32, i32)>,
+}
+
+impl<'a> PlayerResolver<'a> {
+    pub fn new(ltl: &'a LifetimeList<'a>, replay: &Replay) -> Self {
+        Self {
+            ltl,
+            unique_id: get_object_id(replay, "Engine.PlayerReplicationInfo:UniqueId"),
+            references: OWNER_REFERENCES.iter().filter_map(|name| get_object_id(replay, name)).collect(),
+            team: get_object_id(replay, "Engine.PlayerReplicationInfo:Team"),
+            team_objects: [0, 1].iter()
+                .filter_map(|&num| get_object_id(replay, &format!("Archetypes.Teams.Team{}", num)).map(|oid| (oid, num)))
+                .collect(),
+        }
+    }
+
+    /// The player owning the actor at a frame.
+    pub fn resolve(&self, actor_id: i32, frame: usize) -> Option<UniqueId> {
+        self.ltl.actor_at(actor_id, frame).and_then(|lt| self.resolve_lifetime(lt, frame))
+    }
+
+    /// The player owning an actor lifetime at a frame.
+    pub fn resolve_lifetime(&self, lifetime: &'a Lifetime<'a>, frame: usize) -> Option<UniqueId> {
+        self.resolve_pri(lifetime, frame).and_then(|pri| self.unique_id(pri))
+    }
+
+    /// The PlayerReplicationInfo lifetime owning an actor lifetime at a frame.
+    /// A PRI resolves to itself.
+    pub fn resolve_pri(&self, lifetime: &'a Lifetime<'a>, frame: usize) -> Option<&'a Lifetime<'a>> {
+        let mut current = lifetime;
+        let mut current_frame = frame;
+        for _ in 0..MAX_DEPTH {
+            if self.unique_id(current).is_some() {
+                return Some(current);
+            }
+            let (ref_frame, ref_attr) = self.references.iter()
+                .find_map(|&ref_id| reference_at(current, ref_id, current_frame))?;
+            current = self.ltl.resolve_actor_reference(ref_attr, ref_frame)?;
+            current_frame = ref_frame;
+        }
+        None
+    }
+
+    /// The team (0 for blue, 1 for orange) of the player owning an actor lifetime at a frame,
+    /// from their PRI's `Engine.PlayerReplicationInfo:Team`.
+    pub fn team(&self, lifetime: &'a Lifetime<'a>, frame: usize) -> Option<i32> {
+        let pri = self.resolve_pri(lifetime, frame)?;
+        let (ref_frame, ref_attr) = reference_at(pri, self.team?, frame)?;
+        let team_object = self.ltl.resolve_actor_reference(ref_attr, ref_frame)?.object_id()?;
+        self.team_objects.iter().find(|&&(oid, _)| oid == team_object).map(|&(_, num)| num)
+    }
+
+    fn unique_id(&self, pri: &Lifetime) -> Option<UniqueId> {
+        pri.attribute_updates(self.unique_id?).find_map(|(_, attr)| {
+            match attr {
+                Attribute::UniqueId(uid) => Some(*uid.to_owned()),
+                _ => None,
+            }
+        })
+    }
+}
+
+/// The value of a reference attribute at a frame: the last update at or before it, or the
+/// first one if it wasn't set yet (references are often replicated just after the actor is created).
+fn reference_at<'a>(lifetime: &Lifetime<'a>, ref_id: i32, frame: usize) -> Option<(usize, &'a Attribute)> {
+    let mut ret = None;
+    for (ev, attr) in lifetime.attribute_updates(ref_id) {
+        if ret.is_some() && ev.frame > frame {
+            break;
+        }
+        ret = Some((ev.frame, attr));
+    }
+    ret
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fs;
+use std::path::Path;
+
+use crate::person::Person;
+use crate::{Metadata, PlayerResult, PlayerResults};
+
+/// The people on a team, read from a JSON file like
+/// `{"members": [{"name": "Alice", "accounts": ["steam-76561198000000000-0", "epic-0123456789abcdef0123456789abcdef-0"]}]}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Roster {
+    pub members: Vec<Person>,
+}
+
+impl Roster {
+    pub fn load<P: AsRef<Path>>(roster_file: P) -> Result<Self, Box<dyn error::Error>> {
+        let contents = fs::read_to_string(roster_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+/// One member's totals over every replay they played, using the active (non-frozen) time only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderboardRow {
+    pub name: String,
+    pub games: usize,
+    pub ballcam_percent: f32,
+    pub swaps_per_minute: f32,
+}
+
+/// Every member's totals, highest ballcam % first. Members without any games are left out.
+pub fn leaderboard(roster: &Roster, data: &[(Metadata, PlayerResults)]) -> Vec<LeaderboardRow> {
+    let mut ret: Vec<LeaderboardRow> = roster.members.iter()
+        .filter_map(|member| {
+            let mut games = 0;
+            let mut total = PlayerResult::new();
+            for res in data.iter().filter_map(|(_, hm)| member.result_in(hm)) {
+                games += 1;
+                total.ballcam_active_only += res.ballcam_active_only;
+                total.total_time_active_only += res.total_time_active_only;
+                total.swaps_active_only += res.swaps_active_only;
+            }
+            if games == 0 || total.total_time_active_only <= 0f32 {
+                return None;
+            }
+            Some(LeaderboardRow {
+                name: member.name.clone(),
+                games,
+                ballcam_percent: 100f32 * total.ballcam_active_only / total.total_time_active_only,
+                swaps_per_minute: total.swaps_per_minute_active_only(),
+            })
+        })
+        .collect();
+    ret.sort_by(|a, b| b.ballcam_percent.total_cmp(&a.ballcam_percent));
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use crate::player_key::PlayerKey;
+
+    fn result(ballcam: f32, total: f32, swaps: i32) -> PlayerResult {
+        let mut res = PlayerResult::new();
+        res.ballcam_active_only = ballcam;
+        res.total_time_active_only = total;
+        res.swaps_active_only = swaps;
+        res
+    }
+
+    fn replay(results: Vec<(&PlayerKey, PlayerResult)>) -> (Metadata, PlayerResults) {
+        let md = Metadata::new(None, NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(), String::from("TAGame.Replay_Soccar_TA-2"));
+        (md, results.into_iter().map(|(key, res)| (key.clone(), res)).collect())
+    }
+
+    #[test]
+    fn leaderboard_adds_up_every_account() {
+        let steam: PlayerKey = "steam-76561198000000000-0".parse().unwrap();
+        let epic: PlayerKey = "epic-0123456789abcdef0123456789abcdef-0".parse().unwrap();
+        let other: PlayerKey = "steam-76561198000000001-0".parse().unwrap();
+        let roster = Roster {
+            members: vec![
+                Person::new("Alice", vec![steam.clone(), epic.clone()]),
+                Person::new("Bob", vec![other.clone()]),
+                Person::new("Nobody", vec!["steam-1-0".parse().unwrap()]),
+            ],
+        };
+        let data = vec![
+            replay(vec![(&steam, result(60.0, 120.0, 4)), (&other, result(30.0, 300.0, 10))]),
+            replay(vec![(&epic, result(120.0, 180.0, 2))]),
+            // both accounts in one replay (e.g. split screen) count as one game
+            replay(vec![(&steam, result(0.0, 60.0, 0)), (&epic, result(60.0, 60.0, 6))]),
+        ];
+
+        let rows = leaderboard(&roster, &data);
+        assert_eq!(rows.len(), 2);
+
+        assert_eq!(rows[0].name, "Alice");
+        assert_eq!(rows[0].games, 3);
+        // 240s of ballcam over 420s, 12 swaps in 7 minutes
+        assert!((rows[0].ballcam_percent - 100.0 * 240.0 / 420.0).abs() < 1e-4);
+        assert!((rows[0].swaps_per_minute - 12.0 / 7.0).abs() < 1e-4);
+
+        assert_eq!(rows[1].name, "Bob");
+        assert_eq!(rows[1].games, 1);
+        assert!((rows[1].ballcam_percent - 10.0).abs() < 1e-4);
+        assert!((rows[1].swaps_per_minute - 2.0).abs() < 1e-4);
+    }
+}
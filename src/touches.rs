@@ -0,0 +1,241 @@
+use boxcars::{Attribute, Vector3f};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::metrics::{MetricContext, ReplayMetric};
+use crate::player_key::PlayerKey;
+use crate::{get_object_id, Lifetime, ToggleTimeline};
+
+/// How far before and after a touch we look at each player's camera, in seconds.
+pub const TOUCH_WINDOW: f32 = 2.0;
+
+/// A ball touch, see `get_touches` for how they're found. `player` is `None` if the car that
+/// touched it couldn't be told (no car positions replicated).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Touch {
+    pub time: f32,
+    pub frame: usize,
+    pub team: i32,
+    pub player: Option<PlayerKey>,
+}
+
+/// Whose touch it was, from a player's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TouchRelation {
+    Own,
+    Teammate,
+    Opponent,
+}
+
+/// A player's ballcam around a touch: the share of the `TOUCH_WINDOW` before and after it they had
+/// ballcam on. `None` for a window the player wasn't in the game for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TouchBallcam {
+    pub touch: Touch,
+    pub relation: TouchRelation,
+    pub before: Option<f32>,
+    pub after: Option<f32>,
+}
+
+/// Each player's ballcam around every touch while they were in the game.
+pub struct TouchMetric;
+
+impl ReplayMetric for TouchMetric {
+    type Output = Vec<TouchBallcam>;
+
+    fn compute(&self, ctx: &MetricContext) -> HashMap<PlayerKey, Vec<TouchBallcam>> {
+        let touches = get_touches(ctx);
+        ctx.players.iter()
+            .map(|(pid, idx_list)| {
+                let timeline = ToggleTimeline::ballcam(ctx.lifetimes, ctx.replay, pid, idx_list, ctx.disconnects.get(pid));
+                let camera = &ctx.lifetimes.list[idx_list[0]];
+                let around_touches = touches.iter()
+                    .filter_map(|touch| {
+                        let relation = if touch.player.as_ref() == Some(pid) {
+                            TouchRelation::Own
+                        } else if ctx.resolver.team(camera, touch.frame)? == touch.team {
+                            TouchRelation::Teammate
+                        } else {
+                            TouchRelation::Opponent
+                        };
+                        let before = timeline.on_fraction(touch.time - TOUCH_WINDOW, touch.time);
+                        let after = timeline.on_fraction(touch.time, touch.time + TOUCH_WINDOW);
+                        if before.is_none() && after.is_none() {
+                            return None;
+                        }
+                        Some(TouchBallcam {
+                            touch: touch.clone(),
+                            relation,
+                            before,
+                            after,
+                        })
+                    })
+                    .collect();
+                (pid.clone(), around_touches)
+            })
+            .collect()
+    }
+}
+
+/// Smallest change in the ball's velocity between two updates that counts as a hit, in uu/s.
+/// Gravity only changes it by about 20 uu/s between updates.
+const MIN_VELOCITY_CHANGE: f32 = 500.0;
+/// Farthest a car can be from the ball (center to center) for a velocity change to be its touch, in uu.
+const MAX_TOUCH_DISTANCE: f32 = 300.0;
+/// Hits of the same player closer together than this are one touch, in seconds.
+const MIN_TOUCH_GAP: f32 = 0.1;
+
+// every replicated `(frame, time, location, velocity)` of an actor, in order
+type Track = Vec<(usize, f32, Vector3f, Vector3f)>;
+
+fn rigid_body_track(lifetime: &Lifetime, rb_state: i32) -> Track {
+    let still = Vector3f { x: 0f32, y: 0f32, z: 0f32 };
+    lifetime.attribute_updates(rb_state)
+        .filter_map(|(ev, attr)| match attr {
+            Attribute::RigidBody(rb) => Some((ev.frame, ev.time, rb.location, rb.linear_velocity.unwrap_or(still))),
+            _ => None,
+        })
+        .collect()
+}
+
+// the last replicated position at or before a frame
+fn location_at(track: &[(usize, f32, Vector3f, Vector3f)], frame: usize) -> Option<Vector3f> {
+    let ind = track.partition_point(|&(track_frame, _, _, _)| track_frame <= frame);
+    (ind > 0).then(|| track[ind - 1].2)
+}
+
+fn distance(a: &Vector3f, b: &Vector3f) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+/// Every touch in the replay, in order. Touches are found two ways:
+///
+/// - `TAGame.Ball_TA:HitTeamNum` updates. The ball only replicates it when it changes, so these
+///   are just the first touch of each team in a row, given to the closest car of that team.
+/// - The ball's velocity jumping by `MIN_VELOCITY_CHANGE` with a car within `MAX_TOUCH_DISTANCE`,
+///   which also finds the dribbles and follow ups by the same team. Given to the closest car.
+///
+/// Hits of one player less than `MIN_TOUCH_GAP` apart count once.
+pub fn get_touches(ctx: &MetricContext) -> Vec<Touch> {
+    let (ltl, replay) = (ctx.lifetimes, ctx.replay);
+    let Some(hit_team) = get_object_id(replay, "TAGame.Ball_TA:HitTeamNum") else {
+        return vec![];
+    };
+    let rb_state = get_object_id(replay, "TAGame.RBActor_TA:ReplicatedRBState");
+    let track = |lifetime: &Lifetime| rb_state.map(|rb| rigid_body_track(lifetime, rb)).unwrap_or_default();
+    let cars: Vec<(&Lifetime, Track)> = ltl.lifetimes_of_object(replay, "Archetypes.Car.Car_Default")
+        .into_iter()
+        .map(|car| (car, track(car)))
+        .collect();
+
+    // the closest car to the ball at a frame (of the team, if given), with its team
+    let closest_car = |ball_location: &Vector3f, frame: usize, team: Option<i32>| {
+        cars.iter()
+            .filter(|(car, _)| car.start_frame() <= frame && frame <= car.end_frame())
+            .filter_map(|(car, car_track)| Some((*car, ctx.resolver.team(car, frame)?, distance(&location_at(car_track, frame)?, ball_location))))
+            .filter(|&(_, car_team, _)| team.is_none_or(|team| team == car_team))
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+    };
+    let player = |car: &Lifetime, frame: usize| ctx.resolver.resolve_lifetime(car, frame).map(|uid| PlayerKey::from(&uid));
+
+    let mut found: Vec<Touch> = Vec::new();
+    for ball in ltl.list.iter().filter(|lt| lt.attribute_updates(hit_team).next().is_some()) {
+        let ball_track = track(ball);
+        for (ev, attr) in ball.attribute_updates(hit_team) {
+            let Attribute::Byte(team) = attr else {
+                continue;
+            };
+            let team = *team as i32;
+            let toucher = location_at(&ball_track, ev.frame)
+                .and_then(|ball_location| closest_car(&ball_location, ev.frame, Some(team)))
+                .and_then(|(car, _, _)| player(car, ev.frame));
+            found.push(Touch {
+                time: ev.time,
+                frame: ev.frame,
+                team,
+                player: toucher,
+            });
+        }
+        for pair in ball_track.windows(2) {
+            let (before, after) = (&pair[0].3, &pair[1].3);
+            if distance(before, after) < MIN_VELOCITY_CHANGE {
+                continue;
+            }
+            let (frame, time, ball_location) = (pair[1].0, pair[1].1, pair[1].2);
+            if let Some((car, team, dist)) = closest_car(&ball_location, frame, None) {
+                if dist <= MAX_TOUCH_DISTANCE {
+                    found.push(Touch {
+                        time,
+                        frame,
+                        team,
+                        player: player(car, frame),
+                    });
+                }
+            }
+        }
+    }
+    found.sort_by_key(|touch| touch.frame);
+
+    let mut ret: Vec<Touch> = Vec::with_capacity(found.len());
+    for touch in found {
+        let repeat = ret.iter_mut().rev()
+            .take_while(|prev| touch.time - prev.time < MIN_TOUCH_GAP)
+            .find(|prev| prev.team == touch.team
+                && (prev.player.is_none() || touch.player.is_none() || prev.player == touch.player));
+        match repeat {
+            // the same hit found both ways, keep whichever knows who touched it
+            Some(prev) => {
+                if prev.player.is_none() {
+                    prev.player = touch.player;
+                }
+            },
+            None => ret.push(touch),
+        }
+    }
+    ret
+}
+
+/// Mean share of ballcam on before and after touches, over the touches with that window.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TouchWindowSummary {
+    pub touches: usize,
+    pub before: Option<f32>,
+    pub after: Option<f32>,
+}
+
+impl TouchWindowSummary {
+    fn from_touches<'a>(touches: impl Iterator<Item = &'a TouchBallcam>) -> Self {
+        let mut count = 0;
+        let (mut before, mut after): (Vec<f32>, Vec<f32>) = (vec![], vec![]);
+        for touch in touches {
+            count += 1;
+            before.extend(touch.before);
+            after.extend(touch.after);
+        }
+        let mean = |values: &[f32]| (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32);
+        Self {
+            touches: count,
+            before: mean(&before),
+            after: mean(&after),
+        }
+    }
+}
+
+/// A player's ballcam around their own, their teammates' and their opponents' touches.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TouchSummary {
+    pub own: TouchWindowSummary,
+    pub teammate: TouchWindowSummary,
+    pub opponent: TouchWindowSummary,
+}
+
+impl TouchSummary {
+    pub fn from_touches(touches: &[TouchBallcam]) -> Self {
+        let of = |relation: TouchRelation| TouchWindowSummary::from_touches(touches.iter().filter(|tb| tb.relation == relation));
+        Self {
+            own: of(TouchRelation::Own),
+            teammate: of(TouchRelation::Teammate),
+            opponent: of(TouchRelation::Opponent),
+        }
+    }
+}
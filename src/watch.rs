@@ -0,0 +1,115 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::discovery::ReplaySource;
+
+/// How long a replay's size has to stay the same before we consider it fully written.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+/// How often we give a replay that doesn't parse another go before dropping it.
+const MAX_ATTEMPTS: u32 = 5;
+
+struct Pending {
+    size: u64,
+    since: Instant,
+}
+
+/// Watches the roots of a `ReplaySource` (inotify on linux) and hands out the replays that get
+/// saved there, once the game is done writing them.
+pub struct ReplayWatcher {
+    source: ReplaySource,
+    // has to be kept around, the watch stops when it's dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    pending: HashMap<PathBuf, Pending>,
+    failures: HashMap<PathBuf, u32>,
+}
+
+impl ReplayWatcher {
+    pub fn new(source: ReplaySource) -> notify::Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        let mode = if source.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        for root in source.roots.iter() {
+            watcher.watch(root, mode)?;
+        }
+        Ok(Self {
+            source,
+            _watcher: watcher,
+            events,
+            pending: HashMap::new(),
+            failures: HashMap::new(),
+        })
+    }
+
+    /// Block until a new or changed replay hasn't grown for `SETTLE_TIME`, and return it.
+    /// `None` if the watch stopped.
+    pub fn next_replay(&mut self) -> Option<PathBuf> {
+        loop {
+            if let Some(path) = self.take_settled() {
+                return Some(path);
+            }
+            match self.events.recv_timeout(SETTLE_TIME / 4) {
+                Ok(Ok(event)) => {
+                    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        continue;
+                    }
+                    for path in event.paths.iter() {
+                        if self.source.accepts(path) {
+                            self.touch(path, false);
+                        }
+                    }
+                },
+                Ok(Err(e)) => eprintln!("\nHIDDEN ERROR:\n{}\n\n", e),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    /// Check a replay that didn't parse again after the next `SETTLE_TIME`, in case it
+    /// wasn't done being written. Gives up after `MAX_ATTEMPTS`.
+    pub fn retry(&mut self, path: &Path) -> bool {
+        let failures = self.failures.entry(path.to_path_buf()).or_insert(0);
+        *failures += 1;
+        if *failures >= MAX_ATTEMPTS {
+            self.failures.remove(path);
+            return false;
+        }
+        self.touch(path, true);
+        true
+    }
+
+    fn touch(&mut self, path: &Path, restart: bool) {
+        let size = fs::metadata(path).map_or(0, |md| md.len());
+        let pending = self.pending.entry(path.to_path_buf()).or_insert(Pending { size, since: Instant::now() });
+        if pending.size != size || restart {
+            pending.size = size;
+            pending.since = Instant::now();
+        }
+    }
+
+    fn take_settled(&mut self) -> Option<PathBuf> {
+        let now = Instant::now();
+        // moved or deleted before it was done
+        self.pending.retain(|path, _| path.exists());
+        let mut settled = None;
+        for (path, pending) in self.pending.iter_mut() {
+            let size = fs::metadata(path).map_or(0, |md| md.len());
+            if size != pending.size {
+                pending.size = size;
+                pending.since = now;
+            } else if size > 0 && now.duration_since(pending.since) >= SETTLE_TIME {
+                settled = Some(path.clone());
+                break;
+            }
+        }
+        if let Some(path) = &settled {
+            self.pending.remove(path);
+        }
+        settled
+    }
+}